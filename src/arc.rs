@@ -0,0 +1,148 @@
+use std::{
+    borrow::Borrow,
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash},
+    sync::Arc,
+};
+
+use crate::{HashCache, HashCacheConfig, Provider};
+
+/// A [`HashCache`] which hands out shared ownership of its values.
+///
+/// Each lookup clones an [`Arc`] instead of borrowing the cache, so values
+/// can be kept for longer than the cache is borrowed, or even outlive it.
+#[derive(Debug, Clone)]
+pub struct ArcCache<K, V, S = RandomState, F = ()> {
+    cache: HashCache<K, Arc<V>, S, F>,
+}
+
+impl<K, V, S, F> Default for ArcCache<K, V, S, F>
+where
+    S: Default + Clone,
+    F: Default,
+{
+    fn default() -> Self {
+        Self::with_config(HashCacheConfig::default())
+    }
+}
+
+impl<K, V> ArcCache<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        HashCache::with_capacity(capacity).into()
+    }
+}
+
+impl<K, V, S: Clone> ArcCache<K, V, S> {
+    pub fn with_hasher(hasher: S) -> Self {
+        HashCache::with_hasher(hasher).into()
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hasher: S) -> Self {
+        HashCache::with_capacity_and_hasher(capacity, hasher).into()
+    }
+}
+
+impl<K, V, S, F> ArcCache<K, V, S, F> {
+    pub fn with_provider(provider: F) -> Self
+    where
+        S: Default + Clone,
+    {
+        HashCache::with_provider(provider).into()
+    }
+
+    pub fn with_capacity_and_provider(capacity: usize, provider: F) -> Self
+    where
+        S: Default + Clone,
+    {
+        HashCache::with_capacity_and_provider(capacity, provider).into()
+    }
+
+    /// The values are stored as `Arc<V>`, so the weigher and eviction hook
+    /// of the configuration see them as such.
+    pub fn with_config(config: HashCacheConfig<K, Arc<V>, S, F>) -> Self
+    where
+        S: Clone,
+    {
+        HashCache::with_config(config).into()
+    }
+
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    /// The number of entries in the cache, as for [`HashCache::len`].
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    pub fn into_inner(self) -> HashCache<K, Arc<V>, S, F> {
+        self.cache
+    }
+}
+
+impl<K, V, S, F> ArcCache<K, V, S, F>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    pub fn get<Q>(&self, key: &Q) -> Option<Arc<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.get(key).cloned()
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.contains_key(key)
+    }
+
+    pub fn get_or_insert<Q>(&self, key: &Q) -> Arc<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        F: Provider<K, V>,
+    {
+        self.get_or_insert_with(key, |key| self.cache.provider.provide(key))
+    }
+
+    pub fn get_or_insert_with<Q, G>(&self, key: &Q, f: G) -> Arc<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        G: FnOnce(&K) -> V,
+    {
+        Arc::clone(self.cache.get_or_insert_with(key, |key| Arc::new(f(key))))
+    }
+
+    /// Inserts a value into the cache, replacing any existing entry.
+    pub fn insert(&mut self, key: K, value: V) -> Arc<V> {
+        Arc::clone(self.cache.insert(key, Arc::new(value)))
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<Arc<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.remove(key)
+    }
+}
+
+impl<K, V, S, F> From<HashCache<K, Arc<V>, S, F>> for ArcCache<K, V, S, F> {
+    fn from(cache: HashCache<K, Arc<V>, S, F>) -> Self {
+        Self { cache }
+    }
+}
@@ -0,0 +1,190 @@
+//! Coalescing of concurrent insertions of the same key, so that the value for
+//! a key is only computed once even when many threads miss on it at once.
+
+use std::{
+    borrow::Borrow,
+    future, mem,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    task::{Poll, Waker},
+    thread::{self, ThreadId},
+};
+
+/// The set of keys whose values are currently being computed.
+#[derive(Debug)]
+pub(crate) struct Flights<K> {
+    state: Mutex<State<K>>,
+    landed: Condvar,
+}
+
+#[derive(Debug)]
+struct State<K> {
+    /// The keys in flight and the threads leading them, if led synchronously.
+    /// A key is only shared with its leader, and only accessed by waiters
+    /// while the lock is held.
+    keys: Vec<(Arc<K>, Option<ThreadId>)>,
+    /// The tasks waiting for any key to land.
+    wakers: Vec<Waker>,
+}
+
+impl<K> State<K> {
+    fn leader<Q>(&self, key: &Q) -> Option<Option<ThreadId>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        let (_, leader) = self.keys.iter().find(|(k, _)| (**k).borrow() == key)?;
+        Some(*leader)
+    }
+}
+
+impl<K> Flights<K> {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: Mutex::new(State {
+                keys: Vec::new(),
+                wakers: Vec::new(),
+            }),
+            landed: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<K>> {
+        // just ignore poisoning
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Waits until no other thread is computing a value for `key`, then
+    /// returns the result of `check` if it finds one, or else a gate through
+    /// which this thread becomes the leader responsible for computing it.
+    ///
+    /// `check` runs with the flight lock held, so it observes any value a
+    /// previous leader installed before landing.
+    ///
+    /// # Panics
+    ///
+    /// If this thread is already leading `key`, as waiting would deadlock.
+    pub(crate) fn board<Q, R>(
+        &self,
+        key: &Q,
+        mut check: impl FnMut() -> Option<R>,
+    ) -> Result<R, Gate<'_, K>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        let mut state = self.lock();
+        loop {
+            if let Some(found) = check() {
+                return Ok(found);
+            }
+            let Some(leader) = state.leader(key) else {
+                break;
+            };
+            if leader == Some(thread::current().id()) {
+                drop(state);
+                panic!("recursive cache initialization of a key");
+            }
+            state = self.landed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+
+        Err(Gate {
+            flights: self,
+            state,
+        })
+    }
+
+    /// Like `board`, but waits asynchronously, and takes the lead right away
+    /// so that no lock is held once it returns.
+    ///
+    /// The leader is not tied to a thread, so a task which waits on a key it
+    /// is already leading never finishes rather than panicking.
+    pub(crate) async fn board_async<Q, R>(
+        &self,
+        key: &Q,
+        mut check: impl FnMut() -> Option<R>,
+    ) -> Result<R, Leader<'_, K>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        future::poll_fn(|cx| {
+            let mut state = self.lock();
+            if let Some(found) = check() {
+                return Poll::Ready(Ok(found));
+            }
+            if state.leader(key).is_some() {
+                state.wakers.push(cx.waker().clone());
+                return Poll::Pending;
+            }
+            let key = Arc::new(key.to_owned());
+            state.keys.push((Arc::clone(&key), None));
+            Poll::Ready(Err(Leader {
+                flights: self,
+                key: Some(key),
+            }))
+        })
+        .await
+    }
+}
+
+/// A vacant key, held with the flight lock so nobody else can take the lead.
+pub(crate) struct Gate<'a, K> {
+    flights: &'a Flights<K>,
+    state: MutexGuard<'a, State<K>>,
+}
+
+impl<'a, K> Gate<'a, K> {
+    /// Puts `key` in flight, which must be equal to the key boarded with.
+    pub(crate) fn lead(mut self, key: K) -> Leader<'a, K> {
+        let key = Arc::new(key);
+        let leader = Some(thread::current().id());
+        self.state.keys.push((Arc::clone(&key), leader));
+        Leader {
+            flights: self.flights,
+            key: Some(key),
+        }
+    }
+}
+
+/// The responsibility for computing the value of a key in flight.
+///
+/// If dropped without landing (e.g. because the computation panicked, or its
+/// future was dropped), the key is grounded, and one of the waiters for it
+/// takes over as leader.
+pub(crate) struct Leader<'a, K> {
+    flights: &'a Flights<K>,
+    key: Option<Arc<K>>,
+}
+
+impl<K> Leader<'_, K> {
+    pub(crate) fn key(&self) -> &K {
+        self.key.as_ref().unwrap()
+    }
+
+    /// Takes the key out of flight and passes it to `install`, which runs
+    /// before any waiter is woken so that they observe the installed value.
+    pub(crate) fn land<R>(mut self, install: impl FnOnce(K) -> R) -> R {
+        let key = self.key.take().unwrap();
+        let mut state = self.flights.lock();
+        state.keys.retain(|(k, _)| !Arc::ptr_eq(k, &key));
+        let key = Arc::try_unwrap(key)
+            .unwrap_or_else(|_| unreachable!("in-flight keys are only shared under the lock"));
+        let installed = install(key);
+        drop(state);
+        installed
+        // waiters are woken when `self` drops, even if `install` panics
+    }
+}
+
+impl<K> Drop for Leader<'_, K> {
+    fn drop(&mut self) {
+        let mut state = self.flights.lock();
+        if let Some(key) = self.key.take() {
+            state.keys.retain(|(k, _)| !Arc::ptr_eq(k, &key));
+        }
+        let wakers = mem::take(&mut state.wakers);
+        drop(state);
+        self.flights.landed.notify_all();
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
@@ -0,0 +1,99 @@
+use std::{
+    borrow::Borrow,
+    collections::{hash_map, HashMap},
+    hash::{BuildHasher, Hash},
+    ops::Index,
+};
+
+use crate::HashCache;
+
+/// A read-only snapshot of a [`HashCache`], made by [`HashCache::freeze`].
+///
+/// It never changes, so lookups take no lock at all.
+#[derive(Debug, Clone)]
+pub struct FrozenCache<K, V, S> {
+    map: HashMap<K, V, S>,
+}
+
+impl<K, V, S> FrozenCache<K, V, S> {
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn iter(&self) -> hash_map::Iter<'_, K, V> {
+        self.map.iter()
+    }
+
+    pub fn into_inner(self) -> HashMap<K, V, S> {
+        self.map
+    }
+}
+
+impl<K, V, S> FrozenCache<K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.get(key)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.contains_key(key)
+    }
+}
+
+impl<K, V, S, Q> Index<&Q> for FrozenCache<K, V, S>
+where
+    K: Eq + Hash + Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+    S: BuildHasher,
+{
+    type Output = V;
+
+    fn index(&self, key: &Q) -> &V {
+        &self.map[key]
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a FrozenCache<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = hash_map::Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K, V, S> IntoIterator for FrozenCache<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = hash_map::IntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.into_iter()
+    }
+}
+
+impl<K, V, S, F> From<HashCache<K, V, S, F>> for FrozenCache<K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    fn from(cache: HashCache<K, V, S, F>) -> Self {
+        Self {
+            map: cache.into_inner(),
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! A harness applying encoded operations to a [`HashCache`] and checking its
+//! invariants against a model, for fuzz targets to drive.
+//!
+//! A `cargo-fuzz` target need only pass its input through:
+//!
+//! ```ignore
+//! fuzz_target!(|data: &[u8]| simple_cache::fuzzing::fuzz(data));
+//! ```
+
+use std::collections::HashMap;
+
+use crate::{HashCache, HashCacheConfig};
+
+/// An operation on a `HashCache<u32, u32>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Get(u32),
+    GetOrInsert(u32, u32),
+    Remove(u32),
+    Clear,
+    Insert(u32, u32),
+}
+
+impl Op {
+    /// Decodes operations from three bytes each: the operation, then a key
+    /// and value drawn from a small range, so that keys collide often.
+    pub fn decode(data: &[u8]) -> impl Iterator<Item = Op> + '_ {
+        data.chunks_exact(3).map(|op| {
+            let (key, value) = (u32::from(op[1] % 32), u32::from(op[2]));
+            match op[0] % 5 {
+                0 => Op::Get(key),
+                1 => Op::GetOrInsert(key, value),
+                2 => Op::Remove(key),
+                3 => Op::Clear,
+                _ => Op::Insert(key, value),
+            }
+        })
+    }
+}
+
+/// Applies operations decoded from `data`, where the first byte picks an
+/// entry bound, if any, so that eviction is exercised too.
+pub fn fuzz(data: &[u8]) {
+    let Some((&bound, ops)) = data.split_first() else {
+        return;
+    };
+    let max_entries = (bound % 16 != 0).then_some(usize::from(bound % 16));
+    let ops: Vec<_> = Op::decode(ops).collect();
+    apply(&ops, max_entries);
+}
+
+/// Applies `ops` to a new cache, panicking if it disagrees with a model of
+/// what it should hold.
+///
+/// References from consecutive `Get` and `GetOrInsert` operations are all
+/// kept, and checked again before the next operation that needs `&mut`, so a
+/// value which moves or is freed while borrowed is caught (under Miri or a
+/// sanitizer, if not by the check itself).
+///
+/// With `max_entries`, entries may be evicted at any time, so the model only
+/// says what an entry holds if it is still present.
+pub fn apply(ops: &[Op], max_entries: Option<usize>) {
+    let mut cache: HashCache<u32, u32> = HashCache::with_config(HashCacheConfig {
+        max_entries,
+        ..HashCacheConfig::default()
+    });
+    let mut model = HashMap::new();
+
+    let mut ops = ops.iter().peekable();
+    while ops.peek().is_some() {
+        let mut borrowed = Vec::new();
+        while let Some(&&op) = ops.peek() {
+            match op {
+                Op::Get(k) => {
+                    let found = cache.get(&k);
+                    if let Some(v) = found {
+                        assert_eq!(model.get(&k), Some(v), "get {k}");
+                        borrowed.push((v, *v));
+                    } else {
+                        assert!(max_entries.is_some() || !model.contains_key(&k));
+                    }
+                }
+                Op::GetOrInsert(k, v) => {
+                    let found = cache.get_or_insert_with(&k, |_| v);
+                    if max_entries.is_none() {
+                        assert_eq!(*found, *model.entry(k).or_insert(v), "get_or_insert {k}");
+                    } else {
+                        assert!(model.get(&k) == Some(found) || *found == v);
+                    }
+                    model.insert(k, *found);
+                    borrowed.push((found, *found));
+                }
+                _ => break,
+            }
+            ops.next();
+        }
+        for &(v, expected) in &borrowed {
+            assert_eq!(*v, expected, "borrowed value changed");
+        }
+        drop(borrowed);
+
+        match ops.next() {
+            Some(&Op::Remove(k)) => {
+                let removed = cache.remove(&k);
+                let expected = model.remove(&k);
+                if max_entries.is_none() || removed.is_some() {
+                    assert_eq!(removed, expected, "remove {k}");
+                }
+            }
+            Some(Op::Clear) => {
+                cache.clear();
+                model.clear();
+            }
+            Some(&Op::Insert(k, v)) => {
+                assert_eq!(*cache.insert(k, v), v);
+                model.insert(k, v);
+            }
+            _ => {}
+        }
+        check(&mut cache, &model, max_entries);
+    }
+}
+
+/// Checks the cache against the model, with `&mut` access so that evicted
+/// entries are reclaimed first.
+fn check(cache: &mut HashCache<u32, u32>, model: &HashMap<u32, u32>, max_entries: Option<usize>) {
+    cache.shrink_to_fit();
+    let len = cache.len();
+    assert_eq!(len, (&cache.iter()).into_iter().count(), "len");
+    match max_entries {
+        None => assert_eq!(len, model.len(), "len"),
+        Some(max) => assert!(len <= max.max(1), "len {len} over {max}"),
+    }
+    for (k, v) in &cache.iter() {
+        assert_eq!(model.get(k), Some(v), "entry {k}");
+    }
+}
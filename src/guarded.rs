@@ -0,0 +1,345 @@
+use std::{
+    borrow::Borrow,
+    collections::{hash_map::RandomState, BTreeMap, HashMap},
+    fmt,
+    hash::{BuildHasher, Hash},
+    marker::PhantomData,
+    ops::Deref,
+    ptr::NonNull,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Mutex, MutexGuard, RwLock, TryLockError,
+    },
+};
+
+use crate::{PinBox, PoisonPolicy};
+
+/// A cache which hands out [`CacheGuard`]s instead of plain references, so
+/// that it can evict entries while they are being read.
+///
+/// Each guard holds a count on its entry. An entry which is evicted while
+/// guarded is only freed once its last guard is dropped, so bounded eviction
+/// happens under `&self`, concurrently with lookups.
+///
+/// ```
+/// # use simple_cache::GuardedCache;
+/// let cache = GuardedCache::with_max_entries(1);
+/// let one = cache.get_or_insert_with(&1, |_| "one".to_string());
+/// let two = cache.get_or_insert_with(&2, |_| "two".to_string());
+/// assert!(!cache.contains_key(&1));
+/// // still readable after being evicted
+/// assert_eq!(*one, "one");
+/// assert_eq!(*two, "two");
+/// ```
+pub struct GuardedCache<K, V, S = RandomState> {
+    map: RwLock<Map<K, V, S>>,
+    /// Entries evicted while guarded, waiting for their guards to drop.
+    retired: Mutex<Vec<PinBox<Entry<V>>>>,
+    /// Whether `retired` may be non-empty, so lookups can skip its lock.
+    has_retired: AtomicBool,
+    max_entries: Option<usize>,
+    /// The source of recency stamps for LRU eviction.
+    tick: AtomicU64,
+}
+
+struct Map<K, V, S> {
+    /// SAFETY: entries are only freed once no guard for them is live.
+    entries: HashMap<K, PinBox<Entry<V>>, S>,
+    /// Keys by the recency stamp of their entry when queued, least recently
+    /// used first. Stamps only grow, so an entry is never queued behind where
+    /// it belongs now, and is requeued if it has been used since once it
+    /// comes first.
+    recency: BTreeMap<u64, K>,
+}
+
+struct Entry<V> {
+    guards: AtomicUsize,
+    last_used: AtomicU64,
+    /// Where the entry is queued in `recency`.
+    queued: AtomicU64,
+    value: V,
+}
+
+/// A counted reference to a value in a [`GuardedCache`], which keeps the
+/// value alive even if it is evicted.
+pub struct CacheGuard<'a, V> {
+    entry: NonNull<Entry<V>>,
+    marker: PhantomData<&'a V>,
+}
+
+impl<K, V, S: Default> Default for GuardedCache<K, V, S> {
+    fn default() -> Self {
+        Self::with_max_entries_and_hasher(None, S::default())
+    }
+}
+
+impl<K, V> GuardedCache<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A cache which evicts the least recently used entry to stay within
+    /// `max_entries`.
+    pub fn with_max_entries(max_entries: usize) -> Self {
+        Self::with_max_entries_and_hasher(Some(max_entries), RandomState::new())
+    }
+}
+
+impl<K, V, S> GuardedCache<K, V, S> {
+    pub fn with_hasher(hasher: S) -> Self {
+        Self::with_max_entries_and_hasher(None, hasher)
+    }
+
+    pub fn with_max_entries_and_hasher(max_entries: Option<usize>, hasher: S) -> Self {
+        Self {
+            map: RwLock::new(Map {
+                entries: HashMap::with_hasher(hasher),
+                recency: BTreeMap::new(),
+            }),
+            retired: Mutex::new(Vec::new()),
+            has_retired: AtomicBool::new(false),
+            max_entries,
+            tick: AtomicU64::new(0),
+        }
+    }
+
+    /// The number of entries in the cache, not counting evicted entries
+    /// which are still guarded.
+    pub fn len(&self) -> usize {
+        PoisonPolicy::Ignore.read(&self.map).entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&mut self) {
+        let map = self.map.get_mut().unwrap_or_else(|e| e.into_inner());
+        map.entries.clear();
+        map.recency.clear();
+        self.retired().clear();
+    }
+
+    fn retired(&self) -> MutexGuard<'_, Vec<PinBox<Entry<V>>>> {
+        // just ignore poisoning
+        self.retired.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Takes a guard on an entry, which must still be in the map, with the
+    /// map lock held so it cannot be evicted meanwhile.
+    fn guard(&self, entry: &PinBox<Entry<V>>) -> CacheGuard<'_, V> {
+        entry.guards.fetch_add(1, Ordering::Relaxed);
+        entry
+            .last_used
+            .store(self.tick.fetch_add(1, Ordering::Relaxed), Ordering::Relaxed);
+        CacheGuard {
+            entry: NonNull::from(&**entry),
+            marker: PhantomData,
+        }
+    }
+
+    /// Frees an entry which was removed from the map, or else keeps it until
+    /// its guards are dropped. Also frees retired entries no longer guarded.
+    fn retire(&self, entry: PinBox<Entry<V>>) {
+        let mut retired = self.retired();
+        Self::free_unguarded(&mut retired);
+        if entry.guards.load(Ordering::Acquire) != 0 {
+            retired.push(entry);
+        }
+        self.has_retired
+            .store(!retired.is_empty(), Ordering::Relaxed);
+    }
+
+    /// Frees retired entries no longer guarded, unless there are none or
+    /// another thread is already at it.
+    fn reclaim(&self) {
+        if !self.has_retired.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut retired = match self.retired.try_lock() {
+            Ok(retired) => retired,
+            // just ignore poisoning
+            Err(TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(TryLockError::WouldBlock) => return,
+        };
+        Self::free_unguarded(&mut retired);
+        self.has_retired
+            .store(!retired.is_empty(), Ordering::Relaxed);
+    }
+
+    fn free_unguarded(retired: &mut Vec<PinBox<Entry<V>>>) {
+        // no new guards are taken on entries outside the map, so an entry
+        // seen unguarded stays that way
+        retired.retain(|entry| entry.guards.load(Ordering::Acquire) != 0);
+    }
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher> Map<K, V, S> {
+    /// Removes the least recently used entry.
+    fn evict(&mut self) -> Option<PinBox<Entry<V>>> {
+        loop {
+            let (queued, key) = self.recency.pop_first()?;
+            let entry = &self.entries[&key];
+            let last_used = entry.last_used.load(Ordering::Relaxed);
+            if last_used <= queued {
+                return self.entries.remove(&key);
+            }
+            entry.queued.store(last_used, Ordering::Relaxed);
+            self.recency.insert(last_used, key);
+        }
+    }
+
+    fn remove<Q>(&mut self, key: &Q) -> Option<PinBox<Entry<V>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let entry = self.entries.remove(key)?;
+        self.recency.remove(&entry.queued.load(Ordering::Relaxed));
+        Some(entry)
+    }
+}
+
+impl<K, V, S> GuardedCache<K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    pub fn get<Q>(&self, key: &Q) -> Option<CacheGuard<'_, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.reclaim();
+        let map = PoisonPolicy::Ignore.read(&self.map);
+        Some(self.guard(map.entries.get(key)?))
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        PoisonPolicy::Ignore
+            .read(&self.map)
+            .entries
+            .contains_key(key)
+    }
+
+    /// Gets the value for `key`, or computes and inserts it, evicting the
+    /// least recently used entry if the cache is full.
+    ///
+    /// The value is computed without holding any lock, so if another thread
+    /// inserts the key meanwhile, its value is returned instead.
+    pub fn get_or_insert_with<Q, F>(&self, key: &Q, f: F) -> CacheGuard<'_, V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        F: FnOnce(&K) -> V,
+    {
+        if let Some(found) = self.get(key) {
+            return found;
+        }
+        let key = key.to_owned();
+        let value = f(&key);
+
+        let mut map = PoisonPolicy::Ignore.write(&self.map);
+        if let Some(entry) = map.entries.get::<K>(&key) {
+            return self.guard(entry);
+        }
+        let mut evicted = None;
+        if map.entries.len() >= self.max_entries.unwrap_or(usize::MAX) {
+            evicted = map.evict();
+        }
+        let entry = PinBox::new(Box::new(Entry {
+            guards: AtomicUsize::new(0),
+            last_used: AtomicU64::new(0),
+            queued: AtomicU64::new(0),
+            value,
+        }));
+        let guard = self.guard(&entry);
+        let queued = entry.last_used.load(Ordering::Relaxed);
+        entry.queued.store(queued, Ordering::Relaxed);
+        if self.max_entries.is_some() {
+            map.recency
+                .insert(queued, Borrow::<Q>::borrow(&key).to_owned());
+        }
+        map.entries.insert(key, entry);
+        drop(map);
+
+        if let Some(entry) = evicted {
+            self.retire(entry);
+        }
+        guard
+    }
+
+    /// Removes the entry for `key`, returning whether there was one. Values
+    /// still guarded are freed once their guards are dropped.
+    pub fn remove<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let removed = PoisonPolicy::Ignore.write(&self.map).remove(key);
+        match removed {
+            Some(entry) => {
+                self.retire(entry);
+                true
+            }
+            None => {
+                self.reclaim();
+                false
+            }
+        }
+    }
+}
+
+impl<K, V, S> fmt::Debug for GuardedCache<K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GuardedCache")
+            .field("len", &self.len())
+            .field("max_entries", &self.max_entries)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<V> Deref for CacheGuard<'_, V> {
+    type Target = V;
+    fn deref(&self) -> &V {
+        // SAFETY: The entry is not freed while this guard counts on it.
+        unsafe { &self.entry.as_ref().value }
+    }
+}
+
+impl<V> Clone for CacheGuard<'_, V> {
+    fn clone(&self) -> Self {
+        // SAFETY: The entry is not freed while this guard counts on it.
+        unsafe { self.entry.as_ref() }
+            .guards
+            .fetch_add(1, Ordering::Relaxed);
+        Self {
+            entry: self.entry,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<V> Drop for CacheGuard<'_, V> {
+    fn drop(&mut self) {
+        // SAFETY: The entry is not freed while this guard counts on it, and
+        // it is not touched once this guard stops counting.
+        unsafe { self.entry.as_ref() }
+            .guards
+            .fetch_sub(1, Ordering::Release);
+    }
+}
+
+impl<V: fmt::Debug> fmt::Debug for CacheGuard<'_, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+// SAFETY: A guard only shares the value, and counts with atomics.
+unsafe impl<V: Sync> Send for CacheGuard<'_, V> {}
+unsafe impl<V: Sync> Sync for CacheGuard<'_, V> {}
@@ -0,0 +1,110 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash, Hasher},
+};
+
+/// A key together with its precomputed hash, so that it is only hashed once
+/// however many times it is looked up.
+///
+/// Use it as the key of a cache with a [`BuildPrehashed`] hasher, which
+/// takes the precomputed hash as is:
+///
+/// ```
+/// # use simple_cache::{BuildPrehashed, HashCache};
+/// let hasher: BuildPrehashed = BuildPrehashed::default();
+/// let cache = HashCache::with_hasher(hasher.clone());
+///
+/// let key = hasher.hashed("key".to_string());
+/// for _ in 0..1000 {
+///     cache.get_or_insert_with(&key, |key| key.key().len());
+/// }
+/// assert_eq!(cache.get(&key), Some(&3));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Hashed<K> {
+    hash: u64,
+    key: K,
+}
+
+impl<K> Hashed<K> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+}
+
+impl<K: PartialEq> PartialEq for Hashed<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.hash == other.hash && self.key == other.key
+    }
+}
+
+impl<K: Eq> Eq for Hashed<K> {}
+
+impl<K> Hash for Hashed<K> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash);
+    }
+}
+
+/// A hasher for [`Hashed`] keys, which hashes keys with `S` once, when they
+/// are wrapped, and then passes that hash through unchanged.
+#[derive(Debug, Clone, Default)]
+pub struct BuildPrehashed<S = RandomState> {
+    hasher: S,
+}
+
+impl<S: BuildHasher> BuildPrehashed<S> {
+    pub fn new(hasher: S) -> Self {
+        Self { hasher }
+    }
+
+    /// Wraps `key` with its hash.
+    ///
+    /// Only keys wrapped by this hasher, or a clone of it, are found in a
+    /// cache which uses it.
+    pub fn hashed<K: Hash>(&self, key: K) -> Hashed<K> {
+        let mut hasher = self.hasher.build_hasher();
+        key.hash(&mut hasher);
+        Hashed {
+            hash: hasher.finish(),
+            key,
+        }
+    }
+}
+
+impl<S> BuildHasher for BuildPrehashed<S> {
+    type Hasher = Prehashed;
+
+    fn build_hasher(&self) -> Prehashed {
+        Prehashed(0)
+    }
+}
+
+/// The [`Hasher`] of [`BuildPrehashed`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Prehashed(u64);
+
+impl Hasher for Prehashed {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write_u64(&mut self, hash: u64) {
+        self.0 = hash;
+    }
+
+    // only Hashed keys are expected, but anything else still hashes
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0.rotate_left(5) ^ u64::from(byte)).wrapping_mul(0x517c_c1b7_2722_0a95);
+        }
+    }
+}
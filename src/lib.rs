@@ -1,161 +1,4027 @@
 use std::{
+    alloc::{self, Layout},
     borrow::Borrow,
-    collections::{hash_map::RandomState, HashMap},
+    collections::{
+        hash_map::{self, RandomState},
+        BTreeMap, HashMap,
+    },
+    convert::Infallible,
     fmt,
-    hash::{BuildHasher, Hash},
+    future::Future,
+    hash::{BuildHasher, Hash, Hasher},
+    iter,
     marker::PhantomData,
-    ops::Deref,
-    ptr::NonNull,
-    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+    mem::{self, ManuallyDrop},
+    ops::{Deref, DerefMut, Index},
+    ptr::{self, NonNull},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, Receiver, SendError},
+        Arc, LockResult, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard,
+        TryLockError,
+    },
+    time::{Duration, Instant},
+    vec,
 };
 
-#[derive(Debug)]
-pub struct HashCache<K, V, S = RandomState, F = ()> {
+mod arc;
+mod flight;
+mod frozen;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+mod guarded;
+mod hashed;
+mod local;
+mod sketch;
+mod tiered;
+
+pub use arc::ArcCache;
+use flight::Flights;
+pub use frozen::FrozenCache;
+pub use guarded::{CacheGuard, GuardedCache};
+pub use hashed::{BuildPrehashed, Hashed, Prehashed};
+pub use local::ThreadLocalCache;
+use sketch::Sketch;
+pub use tiered::TieredCache;
+
+/// A concurrent cache, whose values stay put while the cache is borrowed.
+///
+/// # Borrowing
+///
+/// Values found or inserted through `&self` borrow the cache, so they can be
+/// held across further lookups and insertions:
+///
+/// ```
+/// # use simple_cache::HashCache;
+/// let cache = HashCache::new();
+/// let one = cache.get_or_insert_with(&1, |n| n.to_string());
+/// let two = cache.get_or_insert_with(&2, |n| n.to_string());
+/// assert_eq!((one.as_str(), two.as_str()), ("1", "2"));
+/// ```
+///
+/// Values are only dropped through `&mut self`, which cannot be taken while
+/// any of them is still borrowed:
+///
+/// ```compile_fail
+/// # use simple_cache::HashCache;
+/// let mut cache = HashCache::new();
+/// let one = cache.get_or_insert_with(&1, |n| n.to_string());
+/// cache.clear();
+/// assert_eq!(one, "1");
+/// ```
+///
+/// ```compile_fail
+/// # use simple_cache::HashCache;
+/// let mut cache = HashCache::new();
+/// cache.insert(1, "1".to_string());
+/// let one = cache.get(&1).unwrap();
+/// cache.remove(&1);
+/// assert_eq!(one, "1");
+/// ```
+///
+/// Nor do values outlive the cache:
+///
+/// ```compile_fail
+/// # use simple_cache::HashCache;
+/// fn lookup(key: u32) -> &'static String {
+///     let cache = HashCache::new();
+///     cache.get_or_insert_with(&key, |n| n.to_string())
+/// }
+/// ```
+///
+/// # Thread safety
+///
+/// The cache is `Send` when `K: Send + Sync`, `V: Send`, and `S` and `F` are
+/// `Send`. It is `Sync` when additionally `V: Sync`, and `S` and `F` are
+/// `Sync`, as a shared cache hands out `&V` to every thread and may drop a
+/// value on another thread than the one which inserted it. Keys are shared
+/// with whichever thread waits on a key in flight, so must be `Sync` either
+/// way. The provider is only ever used by shared reference.
+///
+/// So a cache of non-`Send` values is not `Send`:
+///
+/// ```compile_fail
+/// # use simple_cache::HashCache;
+/// # use std::rc::Rc;
+/// fn assert_send<T: Send>(_: T) {}
+/// assert_send(HashCache::<u32, Rc<u32>>::new());
+/// ```
+///
+/// Nor is a cache of non-`Sync` values `Sync`:
+///
+/// ```compile_fail
+/// # use simple_cache::HashCache;
+/// # use std::cell::Cell;
+/// fn assert_sync<T: Sync>(_: T) {}
+/// assert_sync(HashCache::<u32, Cell<u32>>::new());
+/// ```
+///
+/// Nor is a cache with a non-`Sync` provider, even if its values are:
+///
+/// ```compile_fail
+/// # use simple_cache::{HashCache, Provider};
+/// # use std::{cell::Cell, collections::hash_map::RandomState};
+/// #[derive(Default)]
+/// struct Counting(Cell<u32>);
+/// impl Provider<u32, u32> for Counting {
+///     fn provide(&self, _: &u32) -> u32 {
+///         self.0.replace(self.0.get() + 1)
+///     }
+/// }
+/// fn assert_sync<T: Sync>(_: T) {}
+/// assert_sync(HashCache::<u32, u32, RandomState, Counting>::default());
+/// ```
+///
+/// Without any of these, the cache is both:
+///
+/// ```
+/// # use simple_cache::HashCache;
+/// fn assert_send_sync<T: Send + Sync>(_: T) {}
+/// assert_send_sync(HashCache::<String, Vec<u8>>::new());
+/// ```
+///
+/// # Sets
+///
+/// With zero-sized values, such as `()`, the cache is a concurrent set, and
+/// the values take no allocation:
+///
+/// ```
+/// # use simple_cache::HashCache;
+/// let mut seen: HashCache<String, ()> = HashCache::new();
+/// assert!(!seen.contains_or_insert("a", |_| ()));
+/// assert!(seen.contains_or_insert("a", |_| ()));
+/// assert_eq!(seen.get("a"), Some(&()));
+/// assert_eq!(seen.remove("a"), Some(()));
+/// seen.insert("b".to_string(), ());
+/// seen.clear();
+/// assert!(seen.is_empty());
+/// ```
+pub struct HashCache<K, V: ?Sized, S = RandomState, F = ()> {
     /// SAFETY: produced PinBox value reference lifetimes are bound by &self.
-    arena: RwLock<HashMap<K, PinBox<V>, S>>,
+    shards: Box<[RwLock<Arena<K, V, S>>]>,
+    router: S,
     provider: F,
+    default_ttl: Option<Duration>,
+    eviction: Eviction<K, V>,
+    /// The keys in flight in each shard, so that misses in one shard don't
+    /// wait on those in another.
+    flights: Box<[Flights<K>]>,
+    poison: PoisonPolicy,
+    #[cfg(feature = "stats")]
+    stats: Counters,
+}
+
+#[non_exhaustive]
+pub struct HashCacheConfig<K, V: ?Sized, S = RandomState, F = ()> {
+    pub capacity: usize,
+    /// How many times over a shard's map grows once it is full, so that a
+    /// cache warming up from empty rehashes fewer times, for up to this many
+    /// times the table memory its entries need. Values below 2, the map's
+    /// own growth, are treated as 2.
+    ///
+    /// Rehashing moves the map's slots, but not the values, which are boxed,
+    /// so references to values stay valid however much the map grows.
+    pub growth_factor: usize,
+    pub hasher: S,
+    pub provider: F,
+    /// How long entries live after insertion, or forever if `None`.
+    pub default_ttl: Option<Duration>,
+    /// How long entries live after they were last accessed, or forever if
+    /// `None`. Each lookup which finds an entry pushes its deadline back.
+    ///
+    /// With a TTL as well, an entry expires at whichever deadline is earlier,
+    /// so it is never kept past its TTL however often it is used.
+    ///
+    /// ```
+    /// # use simple_cache::{Clock, HashCache, HashCacheConfig};
+    /// # use std::{sync::{Arc, Mutex}, time::{Duration, Instant}};
+    /// # #[derive(Clone)]
+    /// # struct ManualClock(Arc<Mutex<Instant>>);
+    /// # impl Clock for ManualClock {
+    /// #     fn now(&self) -> Instant {
+    /// #         *self.0.lock().unwrap()
+    /// #     }
+    /// # }
+    /// # let clock = ManualClock(Arc::new(Mutex::new(Instant::now())));
+    /// let config = HashCacheConfig::builder()
+    ///     .time_to_idle(Duration::from_secs(10))
+    ///     .clock(clock.clone())
+    ///     .build();
+    /// let cache: HashCache<u32, u32> = HashCache::with_config(config);
+    ///
+    /// cache.get_or_insert_with(&1, |_| 1);
+    /// cache.get_or_insert_with(&2, |_| 2);
+    /// for _ in 0..10 {
+    ///     *clock.0.lock().unwrap() += Duration::from_secs(5);
+    ///     assert_eq!(cache.get(&1), Some(&1));
+    /// }
+    /// assert_eq!(cache.get(&2), None);
+    /// ```
+    pub time_to_idle: Option<Duration>,
+    /// The most entries the cache holds before evicting one chosen by the
+    /// `eviction_policy`, or unbounded if `None`.
+    ///
+    /// Values handed out by `&self` methods must stay valid, so eviction
+    /// under `&self` is only logical: the evicted entry is treated as absent,
+    /// but its memory is reclaimed by the next `&mut self` method.
+    pub max_entries: Option<usize>,
+    /// Once the cache holds more entries than this, the next `&mut self`
+    /// method evicts entries chosen by the `eviction_policy` in one pass,
+    /// down to the `low_watermark`, and reclaims them right away.
+    ///
+    /// Unlike `max_entries`, this is not enforced by `&self` methods, which
+    /// may grow the cache past it until the next `&mut self` method. Set
+    /// `max_entries` above it to bound the cache in the meantime.
+    pub high_watermark: Option<usize>,
+    /// How far to evict down to once past the `high_watermark`, or to the
+    /// `high_watermark` itself if `None`.
+    pub low_watermark: Option<usize>,
+    pub eviction_policy: EvictionPolicy,
+    /// Computes the weight of an entry once, when it is inserted.
+    ///
+    /// Without a weigher, every entry weighs 1.
+    pub weigher: Option<Box<Weigher<K, V>>>,
+    /// The most total weight the cache holds before evicting entries chosen
+    /// by the `eviction_policy`, or unbounded if `None`. Eviction is logical
+    /// as for `max_entries`.
+    ///
+    /// An entry heavier than this on its own is still inserted, after every
+    /// other entry has been evicted.
+    pub max_weight: Option<usize>,
+    /// Called with each entry the cache reclaims: entries evicted (including
+    /// expired ones), replaced, or dropped by `clear` or `retain`.
+    ///
+    /// Eviction under `&self` is only logical, so this runs once the entry is
+    /// physically reclaimed by a `&mut self` method, when no reference to its
+    /// value can remain. Values returned to the caller, such as by `remove`,
+    /// and the entries of a dropped cache are not passed to it.
+    ///
+    /// ```
+    /// # use simple_cache::{HashCache, HashCacheConfig};
+    /// # use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+    /// let evictions = Arc::new(AtomicUsize::new(0));
+    /// let counter = Arc::clone(&evictions);
+    /// let mut config = HashCacheConfig::default();
+    /// config.max_entries = Some(1);
+    /// config.on_evict = Some(Box::new(move |_, _| {
+    ///     counter.fetch_add(1, Ordering::Relaxed);
+    /// }));
+    /// let mut cache: HashCache<u32, u32> = HashCache::with_config(config);
+    ///
+    /// cache.get_or_insert_with(&1, |_| 1);
+    /// cache.get_or_insert_with(&2, |_| 2);
+    /// // the first entry is evicted, but could still be borrowed
+    /// assert_eq!(evictions.load(Ordering::Relaxed), 0);
+    /// cache.clear();
+    /// assert_eq!(evictions.load(Ordering::Relaxed), 2);
+    /// ```
+    pub on_evict: Option<Box<EvictionHook<K, V>>>,
+    /// The source of the current time for entry expiry, or the system clock
+    /// if `None`.
+    pub clock: Option<Box<dyn Clock + Send + Sync>>,
+    /// How long after an entry expires it may still be returned by
+    /// `get_or_insert` and its variants, while `on_stale` has it revalidated.
+    pub stale_while_revalidate: Option<Duration>,
+    /// Called with the key of each stale entry the first time it is returned,
+    /// to arrange for [`HashCache::revalidate`] to be called with it, without
+    /// blocking the caller. Stale entries are only returned if this is set.
+    ///
+    /// The hook can spawn a task on any executor, or queue the key for a
+    /// thread that polls for work:
+    ///
+    /// ```
+    /// # use simple_cache::{Clock, HashCache, HashCacheConfig};
+    /// # use std::{sync::{Arc, Mutex}, time::{Duration, Instant}};
+    /// # #[derive(Clone)]
+    /// # struct ManualClock(Arc<Mutex<Instant>>);
+    /// # impl Clock for ManualClock {
+    /// #     fn now(&self) -> Instant {
+    /// #         *self.0.lock().unwrap()
+    /// #     }
+    /// # }
+    /// # let clock = ManualClock(Arc::new(Mutex::new(Instant::now())));
+    /// let queue = Arc::new(Mutex::new(Vec::new()));
+    /// let stale = Arc::clone(&queue);
+    /// let config = HashCacheConfig::builder()
+    ///     .default_ttl(Duration::from_secs(60))
+    ///     .stale_while_revalidate(Duration::from_secs(10))
+    ///     .on_stale(move |key| stale.lock().unwrap().push(key))
+    ///     .clock(clock.clone())
+    ///     .build();
+    /// let cache: HashCache<u32, u32> = HashCache::with_config(config);
+    ///
+    /// cache.get_or_insert_with(&1, |_| 1);
+    /// *clock.0.lock().unwrap() += Duration::from_secs(65);
+    /// assert_eq!(cache.get_or_insert_with(&1, |_| 2), &1);
+    ///
+    /// for key in queue.lock().unwrap().drain(..) {
+    ///     cache.revalidate_with(&key, |_| 2);
+    /// }
+    /// assert_eq!(cache.get_or_insert_with(&1, |_| 3), &2);
+    /// ```
+    pub on_stale: Option<Box<StaleHook<K>>>,
+    /// Called with each value as it is inserted, whether computed by the
+    /// provider or passed in, e.g. to write it through to a backing store.
+    ///
+    /// It runs before the value is installed, without any lock held, so slow
+    /// I/O does not block other callers. Values computed by `get_or_compute`
+    /// are written even if the cache is too full to keep them.
+    ///
+    /// So the store can diverge from the cache. Where values for one key are
+    /// computed at once, as by
+    /// [`extend_with_provider`](HashCache::extend_with_provider) alongside a
+    /// lookup, each is written, in whichever order their writers finish, but
+    /// the cache keeps whichever is installed first.
+    ///
+    /// ```
+    /// # use simple_cache::{HashCache, HashCacheConfig};
+    /// # use std::{collections::HashMap, sync::{Arc, Mutex}};
+    /// let store = Arc::new(Mutex::new(HashMap::new()));
+    /// let backing = Arc::clone(&store);
+    /// let config = HashCacheConfig::builder()
+    ///     .writer(move |&k, &v| drop(backing.lock().unwrap().insert(k, v)))
+    ///     .build();
+    /// let mut cache: HashCache<u32, u32> = HashCache::with_config(config);
+    ///
+    /// cache.get_or_insert_with(&1, |_| 10);
+    /// cache.insert(2, 20);
+    /// assert_eq!(store.lock().unwrap()[&1], 10);
+    /// assert_eq!(store.lock().unwrap()[&2], 20);
+    /// ```
+    pub writer: Option<Box<Writer<K, V>>>,
+    /// Whether to keep the allocations of values the cache drops, such as by
+    /// `clear`, and put new values in them instead of allocating, to save
+    /// allocator churn when the cache is emptied and refilled.
+    ///
+    /// The kept allocations are only freed when the cache is dropped.
+    pub reuse_allocations: bool,
+    /// The number of independently locked maps to split entries between.
+    ///
+    /// Inserting into one shard does not block access to the others.
+    /// The capacity and any entry or weight bounds are split evenly between
+    /// the shards, and enforced for each shard separately.
+    pub shards: usize,
+    /// Whether a lock poisoned by a panic while it was held is recovered, or
+    /// propagates the panic. Most panics, such as in a provider, happen with
+    /// no lock held, so poisoning means a hook or weigher panicked.
+    pub on_poison: PoisonPolicy,
+}
+
+impl<K, V: ?Sized, S, F> Default for HashCacheConfig<K, V, S, F>
+where
+    S: Default,
+    F: Default,
+{
+    fn default() -> Self {
+        Self::new(S::default(), F::default())
+    }
+}
+
+impl<K, V: ?Sized, S, F> fmt::Debug for HashCacheConfig<K, V, S, F>
+where
+    S: fmt::Debug,
+    F: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HashCacheConfig")
+            .field("capacity", &self.capacity)
+            .field("growth_factor", &self.growth_factor)
+            .field("hasher", &self.hasher)
+            .field("provider", &self.provider)
+            .field("default_ttl", &self.default_ttl)
+            .field("time_to_idle", &self.time_to_idle)
+            .field("max_entries", &self.max_entries)
+            .field("high_watermark", &self.high_watermark)
+            .field("low_watermark", &self.low_watermark)
+            .field("eviction_policy", &self.eviction_policy)
+            .field("weigher", &self.weigher.as_ref().map(|_| ..))
+            .field("max_weight", &self.max_weight)
+            .field("on_evict", &self.on_evict.as_ref().map(|_| ..))
+            .field("clock", &self.clock.as_ref().map(|_| ..))
+            .field("stale_while_revalidate", &self.stale_while_revalidate)
+            .field("on_stale", &self.on_stale.as_ref().map(|_| ..))
+            .field("writer", &self.writer.as_ref().map(|_| ..))
+            .field("reuse_allocations", &self.reuse_allocations)
+            .field("shards", &self.shards)
+            .field("on_poison", &self.on_poison)
+            .finish()
+    }
+}
+
+/// The source of values for [`HashCache::get_or_insert`].
+///
+/// Any `Fn(&K) -> V` closure is a provider, but a provider can also be a
+/// type of its own:
+///
+/// ```
+/// # use simple_cache::{HashCache, Provider};
+/// # use std::collections::hash_map::RandomState;
+/// struct Lengths;
+///
+/// impl Provider<String, usize> for Lengths {
+///     fn provide(&self, key: &String) -> usize {
+///         key.len()
+///     }
+/// }
+///
+/// let cache: HashCache<String, usize, RandomState, _> = HashCache::with_provider(Lengths);
+/// assert_eq!(cache.get_or_insert("four"), &4);
+/// ```
+pub trait Provider<K, V> {
+    fn provide(&self, key: &K) -> V;
+}
+
+impl<K, V, F> Provider<K, V> for F
+where
+    F: Fn(&K) -> V,
+{
+    fn provide(&self, key: &K) -> V {
+        self(key)
+    }
+}
+
+/// A provider which needs `&mut` access to its state, such as a counter.
+///
+/// Calls to the wrapped `FnMut` are serialized by a lock, which is only held
+/// while it runs, never along with any lock of the cache. It must not need
+/// the cache to provide another value, as the lock is not reentrant.
+///
+/// ```
+/// # use simple_cache::HashCache;
+/// # use std::collections::hash_map::RandomState;
+/// let mut next = 0;
+/// let cache: HashCache<char, u32, RandomState, _> = HashCache::with_provider_mut(move |_: &char| {
+///     next += 1;
+///     next
+/// });
+/// assert_eq!(cache.get_or_insert(&'a'), &1);
+/// assert_eq!(cache.get_or_insert(&'b'), &2);
+/// assert_eq!(cache.get_or_insert(&'a'), &1);
+/// ```
+#[derive(Debug, Default)]
+pub struct ProviderMut<G> {
+    provide: Mutex<G>,
+}
+
+impl<G> ProviderMut<G> {
+    pub fn new(provide: G) -> Self {
+        Self {
+            provide: Mutex::new(provide),
+        }
+    }
+
+    pub fn into_inner(self) -> G {
+        // just ignore poisoning
+        self.provide.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<K, V, G> Provider<K, V> for ProviderMut<G>
+where
+    G: FnMut(&K) -> V,
+{
+    fn provide(&self, key: &K) -> V {
+        // just ignore poisoning
+        let mut provide = self.provide.lock().unwrap_or_else(|e| e.into_inner());
+        provide(key)
+    }
+}
+
+/// The source of the current time for entry expiry.
+///
+/// The cache uses the system clock by default, but a clock can be injected
+/// to control expiry, such as in tests:
+///
+/// ```
+/// # use simple_cache::{Clock, HashCache, HashCacheConfig};
+/// # use std::{sync::{Arc, Mutex}, time::{Duration, Instant}};
+/// #[derive(Clone)]
+/// struct ManualClock(Arc<Mutex<Instant>>);
+///
+/// impl Clock for ManualClock {
+///     fn now(&self) -> Instant {
+///         *self.0.lock().unwrap()
+///     }
+/// }
+///
+/// let clock = ManualClock(Arc::new(Mutex::new(Instant::now())));
+/// let config = HashCacheConfig::builder()
+///     .default_ttl(Duration::from_secs(60))
+///     .clock(clock.clone())
+///     .build();
+/// let cache: HashCache<u32, u32> = HashCache::with_config(config);
+///
+/// cache.get_or_insert_with(&1, |_| 1);
+/// *clock.0.lock().unwrap() += Duration::from_secs(61);
+/// assert_eq!(cache.get(&1), None);
+/// ```
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+/// A function computing the weight of a cache entry.
+pub type Weigher<K, V> = dyn Fn(&K, &V) -> usize + Send + Sync;
+
+/// A function called with each cache entry as it is reclaimed.
+pub type EvictionHook<K, V> = dyn Fn(K, Box<V>) + Send + Sync;
+
+/// A function called with the key of a stale cache entry.
+pub type StaleHook<K> = dyn Fn(K) + Send + Sync;
+
+/// A function called with each value inserted into the cache.
+pub type Writer<K, V> = dyn Fn(&K, &V) + Send + Sync;
+
+/// How a bounded cache chooses which entry to evict.
+///
+/// Expired entries are always evicted first, except by `Clock`, which only
+/// treats them as unreferenced.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Evict the least recently used entry.
+    #[default]
+    Lru,
+    /// Evict the least frequently used entry, breaking ties by recency.
+    Lfu,
+    /// Evict the first unreferenced entry found by a hand sweeping round the
+    /// entries, giving referenced entries a second chance by clearing their
+    /// reference bit as it passes. A cheaper approximation of LRU.
+    Clock,
+    /// Evict only expired entries. Once full of live entries,
+    /// [`HashCache::get_or_compute_with`] returns new values without caching
+    /// them, while methods which must cache a value evict as `Lru` does.
+    Reject,
+    /// Evict the least recently used entry, but only to admit a new entry
+    /// which has been looked up more often, as estimated by a sketch of
+    /// recent lookups. Otherwise, the new entry is evicted straight away,
+    /// so one-off lookups do not push out frequently used entries.
+    ///
+    /// A rejected entry is still returned by the method that inserted it.
+    TinyLfu,
+    /// Evict the least recently used of a few randomly sampled entries, as
+    /// Redis's `allkeys-lru` does. An approximation of LRU which looks at
+    /// fewer entries per eviction; more samples approximate it better.
+    SampledLru { samples: usize },
+}
+
+/// What the cache does on finding one of its locks poisoned.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PoisonPolicy {
+    /// Recover the lock, as the cache's own state is never left broken.
+    #[default]
+    Ignore,
+    /// Panic, to fail fast.
+    Panic,
+}
+
+struct Eviction<K, V: ?Sized> {
+    max_entries: Option<usize>,
+    high_watermark: Option<usize>,
+    low_watermark: Option<usize>,
+    policy: EvictionPolicy,
+    weigher: Option<Arc<Weigher<K, V>>>,
+    max_weight: Option<usize>,
+    on_evict: Option<Arc<EvictionHook<K, V>>>,
+    clock: Option<Arc<dyn Clock + Send + Sync>>,
+    time_to_idle: Option<Duration>,
+    stale_for: Option<Duration>,
+    on_stale: Option<Arc<StaleHook<K>>>,
+    writer: Option<Arc<Writer<K, V>>>,
+    /// The subscriber to eviction events, which hands back the entry if its
+    /// receiver was dropped.
+    events: Mutex<Option<Box<EventSender<K, V>>>>,
+    /// The allocations kept for new values, if reusing them.
+    pool: Option<Mutex<Pool>>,
+    growth_factor: usize,
+}
+
+type EventSender<K, V> = dyn FnMut(K, Box<V>) -> Result<(), (K, Box<V>)> + Send;
+
+impl<K, V: ?Sized> Eviction<K, V> {
+    fn weigh(&self, key: &K, value: &V) -> usize {
+        self.weigher
+            .as_ref()
+            .map_or(1, |weigher| weigher(key, value))
+    }
+
+    fn now(&self) -> Instant {
+        match &self.clock {
+            Some(clock) => clock.now(),
+            None => Instant::now(),
+        }
+    }
+
+    /// A new slot, expiring after `ttl` and/or the time-to-idle.
+    fn slot(&self, value: Option<Box<V>>, ttl: Option<Duration>, tick: u64) -> Slot<V> {
+        let now = self.now();
+        let mut slot = Slot::new(value, ttl.map(|ttl| now + ttl), tick);
+        slot.idle = self.time_to_idle.map(|after| Idle {
+            after,
+            since: now,
+            accessed: AtomicU64::new(0),
+        });
+        slot
+    }
+
+    /// Whether an expired entry may still be served while it is revalidated.
+    fn is_stale(&self, slot: &Slot<V>, now: Instant) -> bool {
+        let (Some(window), Some(_)) = (self.stale_for, &self.on_stale) else {
+            return false;
+        };
+        !slot.evicted
+            && slot
+                .expires_at
+                .map_or(false, |t| t <= now && now < t + window)
+    }
+
+    /// Writes a value through, before it is installed.
+    fn write(&self, key: &K, value: &V) {
+        if let Some(writer) = &self.writer {
+            writer(key, value);
+        }
+    }
+
+    fn events(&self) -> MutexGuard<'_, Option<Box<EventSender<K, V>>>> {
+        // just ignore poisoning
+        self.events.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Whether arenas ever evict entries to stay within bounds.
+    fn is_bounded(&self) -> bool {
+        self.max_entries.is_some() || self.max_weight.is_some() || self.high_watermark.is_some()
+    }
+
+    /// Whether reclaimed entries are handed to anyone, rather than dropped.
+    fn is_observed(&self) -> bool {
+        self.on_evict.is_some() || self.events().is_some()
+    }
+
+    /// Drops a reclaimed entry, by way of the eviction events or hook if
+    /// there are any.
+    fn reclaim(&self, key: K, value: PinBox<V>) {
+        let mut entry = (key, value.into_box());
+        let mut events = self.events();
+        if let Some(send) = &mut *events {
+            match send(entry.0, entry.1) {
+                Ok(()) => return,
+                Err(unsent) => {
+                    entry = unsent;
+                    *events = None;
+                }
+            }
+        }
+        drop(events);
+        match &self.on_evict {
+            Some(on_evict) => on_evict(entry.0, entry.1),
+            None => self.recycle(entry.1),
+        }
+    }
+
+    fn pool(pool: &Mutex<Pool>) -> MutexGuard<'_, Pool> {
+        // just ignore poisoning
+        pool.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Drops a value, keeping its allocation if reusing them.
+    fn recycle(&self, value: Box<V>) {
+        match &self.pool {
+            Some(pool) => Self::pool(pool).recycle(value),
+            None => drop(value),
+        }
+    }
+
+    /// Boxes a value, in a kept allocation if there is one.
+    fn boxed(&self, value: V) -> Box<V>
+    where
+        V: Sized,
+    {
+        match &self.pool {
+            Some(pool) => Self::pool(pool).boxed(value),
+            None => Box::new(value),
+        }
+    }
+}
+
+/// The allocations of dropped values, each with the layout it was made for.
+#[derive(Debug, Default)]
+struct Pool {
+    free: Vec<(NonNull<u8>, Layout)>,
+}
+
+impl Pool {
+    fn recycle<T: ?Sized>(&mut self, value: Box<T>) {
+        let layout = Layout::for_value(&*value);
+        let ptr = Box::into_raw(value);
+        // SAFETY: The value is dropped once, here, and the allocation is
+        // only ever reused for a value of the same layout, or freed.
+        unsafe { ptr::drop_in_place(ptr) };
+        match NonNull::new(ptr.cast::<u8>()) {
+            // zero-sized values were never allocated
+            Some(ptr) if layout.size() != 0 => self.free.push((ptr, layout)),
+            _ => {}
+        }
+    }
+
+    fn boxed<T>(&mut self, value: T) -> Box<T> {
+        let layout = Layout::new::<T>();
+        match self.free.iter().rposition(|&(_, free)| free == layout) {
+            Some(i) => {
+                let ptr = self.free.swap_remove(i).0.as_ptr().cast::<T>();
+                // SAFETY: The allocation was made by the global allocator, for
+                // a value of this layout, and is no longer used.
+                unsafe {
+                    ptr.write(value);
+                    Box::from_raw(ptr)
+                }
+            }
+            None => Box::new(value),
+        }
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        for (ptr, layout) in self.free.drain(..) {
+            // SAFETY: The allocation was made by the global allocator with
+            // this layout, and its value was already dropped.
+            unsafe { alloc::dealloc(ptr.as_ptr(), layout) };
+        }
+    }
+}
+
+// SAFETY: The pool only holds allocations, which have no owner but it.
+unsafe impl Send for Pool {}
+
+impl<K, V: ?Sized> Clone for Eviction<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            max_entries: self.max_entries,
+            high_watermark: self.high_watermark,
+            low_watermark: self.low_watermark,
+            policy: self.policy,
+            weigher: self.weigher.clone(),
+            max_weight: self.max_weight,
+            on_evict: self.on_evict.clone(),
+            clock: self.clock.clone(),
+            time_to_idle: self.time_to_idle,
+            stale_for: self.stale_for,
+            on_stale: self.on_stale.clone(),
+            writer: self.writer.clone(),
+            events: Mutex::new(None),
+            pool: self.pool.as_ref().map(|_| Mutex::default()),
+            growth_factor: self.growth_factor,
+        }
+    }
+}
+
+impl<K, V: ?Sized> fmt::Debug for Eviction<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Eviction")
+            .field("max_entries", &self.max_entries)
+            .field("high_watermark", &self.high_watermark)
+            .field("low_watermark", &self.low_watermark)
+            .field("policy", &self.policy)
+            .field("weigher", &self.weigher.as_ref().map(|_| ..))
+            .field("max_weight", &self.max_weight)
+            .field("on_evict", &self.on_evict.as_ref().map(|_| ..))
+            .field("clock", &self.clock.as_ref().map(|_| ..))
+            .field("time_to_idle", &self.time_to_idle)
+            .field("stale_for", &self.stale_for)
+            .field("on_stale", &self.on_stale.as_ref().map(|_| ..))
+            .field("writer", &self.writer.as_ref().map(|_| ..))
+            .field("events", &self.events().as_ref().map(|_| ..))
+            .field("pool", &self.pool)
+            .field("growth_factor", &self.growth_factor)
+            .finish()
+    }
+}
+
+impl<K, V: ?Sized, S, F> HashCacheConfig<K, V, S, F> {
+    fn new(hasher: S, provider: F) -> Self {
+        Self {
+            capacity: 0,
+            growth_factor: 2,
+            hasher,
+            provider,
+            default_ttl: None,
+            time_to_idle: None,
+            max_entries: None,
+            high_watermark: None,
+            low_watermark: None,
+            eviction_policy: EvictionPolicy::default(),
+            weigher: None,
+            max_weight: None,
+            on_evict: None,
+            clock: None,
+            stale_while_revalidate: None,
+            on_stale: None,
+            writer: None,
+            reuse_allocations: false,
+            shards: 1,
+            on_poison: PoisonPolicy::default(),
+        }
+    }
+
+    /// Maps the hasher and provider, which may change their types.
+    fn map_parts<S2, F2>(self, f: impl FnOnce(S, F) -> (S2, F2)) -> HashCacheConfig<K, V, S2, F2> {
+        let HashCacheConfig {
+            capacity,
+            growth_factor,
+            hasher,
+            provider,
+            default_ttl,
+            time_to_idle,
+            max_entries,
+            high_watermark,
+            low_watermark,
+            eviction_policy,
+            weigher,
+            max_weight,
+            on_evict,
+            clock,
+            stale_while_revalidate,
+            on_stale,
+            writer,
+            reuse_allocations,
+            shards,
+            on_poison,
+        } = self;
+        let (hasher, provider) = f(hasher, provider);
+        HashCacheConfig {
+            capacity,
+            growth_factor,
+            hasher,
+            provider,
+            default_ttl,
+            time_to_idle,
+            max_entries,
+            high_watermark,
+            low_watermark,
+            eviction_policy,
+            weigher,
+            max_weight,
+            on_evict,
+            clock,
+            stale_while_revalidate,
+            on_stale,
+            writer,
+            reuse_allocations,
+            shards,
+            on_poison,
+        }
+    }
+}
+
+impl<K, V: ?Sized> HashCacheConfig<K, V> {
+    /// Starts building a configuration from the defaults.
+    ///
+    /// ```
+    /// # use simple_cache::{HashCache, HashCacheConfig};
+    /// let config = HashCacheConfig::builder()
+    ///     .capacity(64)
+    ///     .max_entries(64)
+    ///     .provider(|k: &u32| k * 2)
+    ///     .build();
+    /// let cache: HashCache<u32, u32, _, _> = HashCache::with_config(config);
+    /// assert_eq!(cache.get_or_insert(&21), &42);
+    /// ```
+    pub fn builder() -> HashCacheConfigBuilder<K, V> {
+        HashCacheConfigBuilder {
+            config: HashCacheConfig::default(),
+        }
+    }
+}
+
+/// A builder for [`HashCacheConfig`], from [`HashCacheConfig::builder`].
+///
+/// Each setter corresponds to the field of the same name.
+#[derive(Debug)]
+pub struct HashCacheConfigBuilder<K, V: ?Sized, S = RandomState, F = ()> {
+    config: HashCacheConfig<K, V, S, F>,
+}
+
+impl<K, V: ?Sized, S, F> HashCacheConfigBuilder<K, V, S, F> {
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.config.capacity = capacity;
+        self
+    }
+
+    pub fn growth_factor(mut self, growth_factor: usize) -> Self {
+        self.config.growth_factor = growth_factor;
+        self
+    }
+
+    pub fn hasher<S2>(self, hasher: S2) -> HashCacheConfigBuilder<K, V, S2, F> {
+        HashCacheConfigBuilder {
+            config: self.config.map_parts(|_, provider| (hasher, provider)),
+        }
+    }
+
+    pub fn provider<F2>(self, provider: F2) -> HashCacheConfigBuilder<K, V, S, F2> {
+        HashCacheConfigBuilder {
+            config: self.config.map_parts(|hasher, _| (hasher, provider)),
+        }
+    }
+
+    pub fn default_ttl(mut self, ttl: Duration) -> Self {
+        self.config.default_ttl = Some(ttl);
+        self
+    }
+
+    pub fn time_to_idle(mut self, tti: Duration) -> Self {
+        self.config.time_to_idle = Some(tti);
+        self
+    }
+
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.config.max_entries = Some(max_entries);
+        self
+    }
+
+    pub fn high_watermark(mut self, high_watermark: usize) -> Self {
+        self.config.high_watermark = Some(high_watermark);
+        self
+    }
+
+    pub fn low_watermark(mut self, low_watermark: usize) -> Self {
+        self.config.low_watermark = Some(low_watermark);
+        self
+    }
+
+    pub fn eviction_policy(mut self, policy: EvictionPolicy) -> Self {
+        self.config.eviction_policy = policy;
+        self
+    }
+
+    pub fn weigher(mut self, weigher: impl Fn(&K, &V) -> usize + Send + Sync + 'static) -> Self {
+        self.config.weigher = Some(Box::new(weigher));
+        self
+    }
+
+    pub fn max_weight(mut self, max_weight: usize) -> Self {
+        self.config.max_weight = Some(max_weight);
+        self
+    }
+
+    pub fn on_evict(mut self, on_evict: impl Fn(K, Box<V>) + Send + Sync + 'static) -> Self {
+        self.config.on_evict = Some(Box::new(on_evict));
+        self
+    }
+
+    pub fn clock(mut self, clock: impl Clock + Send + Sync + 'static) -> Self {
+        self.config.clock = Some(Box::new(clock));
+        self
+    }
+
+    pub fn stale_while_revalidate(mut self, window: Duration) -> Self {
+        self.config.stale_while_revalidate = Some(window);
+        self
+    }
+
+    pub fn on_stale(mut self, on_stale: impl Fn(K) + Send + Sync + 'static) -> Self {
+        self.config.on_stale = Some(Box::new(on_stale));
+        self
+    }
+
+    pub fn writer(mut self, writer: impl Fn(&K, &V) + Send + Sync + 'static) -> Self {
+        self.config.writer = Some(Box::new(writer));
+        self
+    }
+
+    pub fn reuse_allocations(mut self, reuse: bool) -> Self {
+        self.config.reuse_allocations = reuse;
+        self
+    }
+
+    pub fn shards(mut self, shards: usize) -> Self {
+        self.config.shards = shards;
+        self
+    }
+
+    pub fn on_poison(mut self, policy: PoisonPolicy) -> Self {
+        self.config.on_poison = policy;
+        self
+    }
+
+    pub fn build(self) -> HashCacheConfig<K, V, S, F> {
+        self.config
+    }
+}
+
+#[derive(Debug)]
+struct Arena<K, V: ?Sized, S> {
+    /// Slots are boxed so that `order` can point to them.
+    map: HashMap<K, PinBox<Slot<V>>, S>,
+    /// Entries logically removed under &self. They may still be borrowed, so
+    /// are only dropped on the next &mut self access.
+    retired: Vec<(K, PinBox<V>)>,
+    tally: Tally,
+    order: Order<V>,
+    /// The source of recency stamps for LRU eviction.
+    tick: AtomicU64,
+    /// How often keys are looked up, for TinyLFU admission.
+    sketch: Option<Sketch>,
+    /// The xorshift state picking entries for sampled eviction.
+    rng: u64,
+}
+
+impl<K, V: ?Sized, S> Arena<K, V, S> {
+    fn new(map: HashMap<K, PinBox<Slot<V>>, S>, eviction: &Eviction<K, V>) -> Self {
+        let sketch = match eviction.policy {
+            EvictionPolicy::TinyLfu => Some(Sketch::new(eviction.max_entries.unwrap_or(0))),
+            _ => None,
+        };
+        let mut order = Order::new(eviction);
+        for slot in map.values().filter(|slot| !slot.evicted) {
+            order.link(slot.ptr());
+        }
+        Self {
+            map,
+            retired: Vec::new(),
+            tally: Tally::default(),
+            order,
+            tick: AtomicU64::new(0),
+            sketch,
+            rng: RandomState::new().build_hasher().finish() | 1,
+        }
+    }
+
+    fn random(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+
+    fn hash<Q>(&self, key: &Q) -> u64
+    where
+        Q: Hash + ?Sized,
+        S: BuildHasher,
+    {
+        let mut hasher = self.map.hasher().build_hasher();
+        key.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn tick(&self) -> u64 {
+        self.tick.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn touch(&self, slot: &Slot<V>, now: Instant) {
+        if let Some(idle) = &slot.idle {
+            let accessed = now.saturating_duration_since(idle.since).as_nanos();
+            let accessed = u64::try_from(accessed).unwrap_or(u64::MAX);
+            idle.accessed.fetch_max(accessed, Ordering::Relaxed);
+        }
+        slot.last_used.store(self.tick(), Ordering::Relaxed);
+        slot.uses.fetch_add(1, Ordering::Relaxed);
+        slot.referenced.store(true, Ordering::Relaxed);
+    }
+
+    /// Finds the live slot for a key, marking it as used.
+    fn find<Q>(&self, key: &Q, now: Instant) -> Option<&Slot<V>>
+    where
+        K: Eq + Hash + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        S: BuildHasher,
+    {
+        if let Some(sketch) = &self.sketch {
+            sketch.increment(self.hash(key));
+        }
+        self.find_again(key, now)
+    }
+
+    /// Like `find`, but for a key which was already looked up, so that the
+    /// lookup is not counted twice for TinyLFU admission.
+    fn find_again<Q>(&self, key: &Q, now: Instant) -> Option<&Slot<V>>
+    where
+        K: Eq + Hash + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        S: BuildHasher,
+    {
+        let slot = self.map.get(key).filter(|slot| slot.is_live(now))?;
+        self.touch(slot, now);
+        Some(&**slot)
+    }
+
+    /// Evicts entries until there is room for one more live entry of the
+    /// given weight.
+    fn make_room(&mut self, eviction: &Eviction<K, V>, weight: usize) {
+        let now = eviction.now();
+        while self.is_full(eviction, weight) {
+            match self.victim(eviction, now) {
+                Some(slot) => self.evict_linked(slot),
+                None => break,
+            }
+        }
+    }
+
+    /// Evicts entries down to the low watermark, once past the high one.
+    fn trim(&mut self, eviction: &Eviction<K, V>) {
+        if let Some(high) = eviction.high_watermark {
+            if self.map.len() - self.tally.evicted > high {
+                self.evict_down_to(eviction, eviction.low_watermark.unwrap_or(high));
+            }
+        }
+    }
+
+    /// Evicts entries until at most `target` live entries remain.
+    fn evict_down_to(&mut self, eviction: &Eviction<K, V>, target: usize) {
+        let now = eviction.now();
+        while self.map.len() - self.tally.evicted > target {
+            match self.victim(eviction, now) {
+                Some(slot) => self.evict_linked(slot),
+                None => break,
+            }
+        }
+    }
+
+    /// The next live slot the eviction policy would evict.
+    fn victim(&mut self, eviction: &Eviction<K, V>, now: Instant) -> Option<NonNull<Slot<V>>> {
+        match eviction.policy {
+            EvictionPolicy::Clock => self.order.clock_hand(now),
+            EvictionPolicy::SampledLru { samples } => self.sample(samples, now),
+            EvictionPolicy::Lru
+            | EvictionPolicy::Lfu
+            | EvictionPolicy::Reject
+            | EvictionPolicy::TinyLfu => self.order.coldest(now),
+        }
+    }
+
+    /// Evicts a slot linked in `order`.
+    fn evict_linked(&mut self, slot: NonNull<Slot<V>>) {
+        // SAFETY: Linked slots are in the map, which nothing else borrows
+        // while we have &mut self.
+        let slot = unsafe { &mut *slot.as_ptr() };
+        self.order.unlink(slot);
+        slot.evict(&mut self.tally);
+    }
+
+    /// Like `make_room`, but only evicts expired entries, and returns whether
+    /// that made enough room.
+    fn make_room_expired(&mut self, eviction: &Eviction<K, V>, weight: usize) -> bool {
+        let now = eviction.now();
+        while self.is_full(eviction, weight) {
+            match self.order.expired(now) {
+                Some(slot) => self.evict_linked(slot),
+                None => return false,
+            }
+        }
+        true
+    }
+
+    /// The number of entries which can still be served, including negative
+    /// ones, even before the rest are reclaimed.
+    fn live_len(&self, eviction: &Eviction<K, V>, now: Instant) -> usize {
+        let expired = self
+            .order
+            .deadlines
+            .range(..=(now, usize::MAX))
+            .filter(|(_, slot)| {
+                // SAFETY: Linked slots are in the map, which we have & access to.
+                let slot = unsafe { slot.as_ref() };
+                slot.is_expired(now) && !eviction.is_stale(slot, now)
+            })
+            .count();
+        self.map.len() - self.tally.evicted - expired
+    }
+
+    /// Whether there is no room for one more live entry of the given weight.
+    fn is_full(&self, eviction: &Eviction<K, V>, weight: usize) -> bool {
+        let max_entries = eviction.max_entries.unwrap_or(usize::MAX);
+        let max_weight = eviction.max_weight.unwrap_or(usize::MAX);
+        self.map.len() - self.tally.evicted >= max_entries
+            || self.tally.weight.saturating_add(weight) > max_weight
+    }
+
+    /// Picks the least recently used of `samples` linked slots, chosen at
+    /// random with replacement.
+    fn sample(&mut self, samples: usize, now: Instant) -> Option<NonNull<Slot<V>>> {
+        let live = self.order.live.len() as u64;
+        if live == 0 {
+            return None;
+        }
+        let picks: Vec<u64> = (0..samples.max(1)).map(|_| self.random() % live).collect();
+        picks
+            .into_iter()
+            .map(|i| self.order.live[i as usize])
+            // SAFETY: Linked slots are in the map, which we have &mut access to.
+            .min_by_key(|ptr| unsafe { ptr.as_ref() }.rank(EvictionPolicy::Lru, now))
+    }
+
+    fn reclaim_retired(&mut self, eviction: &Eviction<K, V>) {
+        for (key, value) in self.retired.drain(..) {
+            eviction.reclaim(key, value);
+        }
+    }
+
+    fn clear(&mut self, eviction: &Eviction<K, V>) {
+        self.tally = Tally::default();
+        self.order.clear();
+        self.reclaim_retired(eviction);
+        for (key, slot) in self.map.drain() {
+            if let Some(value) = slot.into_box().value {
+                eviction.reclaim(key, value);
+            }
+        }
+    }
+}
+
+/// Relinks and recounts every slot in an arena's map once dropped, after the
+/// map is rebuilt, even if that was cut short by a panic.
+struct Relink<'a, K, V: ?Sized, S>(&'a mut Arena<K, V, S>);
+
+impl<K, V: ?Sized, S> Drop for Relink<'_, K, V, S> {
+    fn drop(&mut self) {
+        let arena = &mut *self.0;
+        arena.tally = Tally::default();
+        arena.order.clear();
+        for slot in arena.map.values_mut() {
+            slot.link = None;
+            if slot.evicted {
+                arena.tally.evicted += 1;
+            } else {
+                arena.tally.weight += slot.weight;
+                arena.order.link(slot.ptr());
+            }
+        }
+    }
+}
+
+impl<K: Clone, V: Clone, S: Clone> Arena<K, V, S> {
+    /// A copy of the arena's slots, keeping its running totals, which count
+    /// the evicted slots copied along with the rest.
+    fn duplicate(&self, eviction: &Eviction<K, V>) -> Self {
+        let mut arena = Self::new(self.map.clone(), eviction);
+        arena.tally = self.tally.clone();
+        arena.tick = AtomicU64::new(self.tick.load(Ordering::Relaxed));
+        arena
+    }
+}
+
+impl<K, V: ?Sized, S> Arena<K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    /// Physically drops everything logically removed under &self, and every
+    /// expired entry which is not being served stale.
+    fn sweep(&mut self, eviction: &Eviction<K, V>) {
+        self.reclaim_retired(eviction);
+        self.trim(eviction);
+        let now = eviction.now();
+        // entries are only served stale within the window after they expire
+        let expired_by = match (eviction.stale_for, &eviction.on_stale) {
+            (Some(window), Some(_)) => now.checked_sub(window),
+            _ => Some(now),
+        };
+        while let Some(slot) = expired_by.and_then(|t| self.order.expired(t)) {
+            // SAFETY: Linked slots are in the map, which we have &mut access to.
+            if eviction.is_stale(unsafe { slot.as_ref() }, now) {
+                break;
+            }
+            self.evict_linked(slot);
+        }
+        if self.tally.evicted > 0 {
+            self.retain(eviction, |_, slot| !slot.evicted);
+        }
+    }
+
+    fn retain(&mut self, eviction: &Eviction<K, V>, mut f: impl FnMut(&K, &Slot<V>) -> bool) {
+        if !eviction.is_observed() {
+            let (tally, order) = (&mut self.tally, &mut self.order);
+            self.map.retain(|k, slot| {
+                let keep = f(k, slot);
+                if !keep {
+                    order.unlink(slot);
+                    tally.forget(slot);
+                    if let Some(value) = slot.value.take() {
+                        eviction.recycle(value.into_box());
+                    }
+                }
+                keep
+            });
+            return;
+        }
+
+        // the key is handed on by value, which HashMap::retain can't give, so
+        // the map is rebuilt. A panic in `f` or in hashing a key drops the
+        // slots still being moved, so whatever is left is relinked in any case.
+        let relink = Relink(self);
+        let (kept, dropped): (Vec<_>, Vec<_>) =
+            relink.0.map.drain().partition(|(k, slot)| f(k, slot));
+        relink.0.map.extend(kept);
+        drop(relink);
+        for (key, slot) in dropped {
+            if let Some(value) = slot.into_box().value {
+                eviction.reclaim(key, value);
+            }
+        }
+    }
+
+    /// Inserts the slot, replacing any previous slot for the key. The value
+    /// replaced may still be borrowed, so it is retired rather than dropped.
+    fn install(&mut self, key: K, mut slot: Slot<V>, eviction: &Eviction<K, V>) -> &mut Slot<V> {
+        // take out the slot we're replacing first, so it isn't counted as live
+        if let Some((old_key, old)) = self.map.remove_entry(&key) {
+            let mut old = *old.into_box();
+            self.order.unlink(&mut old);
+            self.tally.forget(&old);
+            if let Some(value) = old.value {
+                self.retired.push((old_key, value));
+            }
+        }
+        slot.weight = match &slot.value {
+            Some(value) => eviction.weigh(&key, value),
+            None => 0,
+        };
+        if self.sketch.is_some() {
+            slot.hash = self.hash(&key);
+        }
+        let admitted = self.admit(slot.hash, eviction, slot.weight);
+        if admitted {
+            self.make_room(eviction, slot.weight);
+        }
+        self.tally.weight += slot.weight;
+        if !admitted {
+            slot.evict(&mut self.tally);
+        }
+        let len = self.map.len();
+        if len == self.map.capacity() && eviction.growth_factor > 2 {
+            self.map
+                .reserve(len.saturating_mul(eviction.growth_factor - 1));
+        }
+        let slot = self.map.entry(key).or_insert(PinBox::new(Box::new(slot)));
+        if !slot.evicted {
+            self.order.link(slot.ptr());
+        }
+        slot
+    }
+
+    /// Whether to admit a new entry for the key with the given hash, which
+    /// for TinyLFU must be more popular than the entry it would evict.
+    fn admit(&mut self, hash: u64, eviction: &Eviction<K, V>, weight: usize) -> bool {
+        if self.sketch.is_none() || !self.is_full(eviction, weight) {
+            return true;
+        }
+        let now = eviction.now();
+        let Some(victim) = self.order.coldest(now) else {
+            return true;
+        };
+        // SAFETY: Linked slots are in the map, which we have &mut access to.
+        let victim = unsafe { victim.as_ref() };
+        let sketch = self.sketch.as_ref().unwrap();
+        victim.is_expired(now) || sketch.estimate(hash) > sketch.estimate(victim.hash)
+    }
+
+    fn remove<Q>(&mut self, key: &Q) -> Option<(K, Slot<V>)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (key, slot) = self.map.remove_entry(key)?;
+        let mut slot = *slot.into_box();
+        self.order.unlink(&mut slot);
+        self.tally.forget(&slot);
+        Some((key, slot))
+    }
+}
+
+/// Running totals over the slots in an arena.
+#[derive(Debug, Default, Clone)]
+struct Tally {
+    /// The number of slots marked as evicted.
+    evicted: usize,
+    /// The total weight of slots not marked as evicted.
+    weight: usize,
+}
+
+impl Tally {
+    /// Stops counting a slot which is being removed from the map.
+    fn forget<V: ?Sized>(&mut self, slot: &Slot<V>) {
+        if slot.evicted {
+            self.evicted -= 1;
+        } else {
+            self.weight -= slot.weight;
+        }
+    }
+}
+
+/// The live slots of an arena, indexed so that eviction and expiry can find
+/// their victims without walking the whole map.
+///
+/// SAFETY: Slots are linked once boxed in the map, and unlinked before they
+/// are evicted or taken out of it. The pointers are only followed with &mut
+/// access to the arena, or with & access to read the slots.
+#[derive(Debug)]
+struct Order<V: ?Sized> {
+    /// Only bounded arenas evict to make room, so only they keep `live` and
+    /// `ranked`. Any arena reclaims expired slots by way of `deadlines`.
+    bounded: bool,
+    policy: EvictionPolicy,
+    /// Every linked slot of a bounded arena, in no particular order.
+    live: Vec<NonNull<Slot<V>>>,
+    /// Where the CLOCK sweep resumes, in `live`.
+    hand: usize,
+    /// Slots by their usage when they were queued, least recently (or for
+    /// Lfu, frequently) used first. Usage only grows, so a slot is never
+    /// queued behind where it belongs now, and is requeued if it has been
+    /// used since once it comes first.
+    ranked: BTreeMap<(u64, u64, usize), NonNull<Slot<V>>>,
+    /// Slots which can expire, by their deadline when they were queued, which
+    /// likewise only moves later.
+    deadlines: BTreeMap<(Instant, usize), NonNull<Slot<V>>>,
+}
+
+/// Where a slot is filed in its arena's `Order`.
+#[derive(Debug)]
+struct Link {
+    pos: Option<usize>,
+    rank: Option<(u64, u64, usize)>,
+    deadline: Option<(Instant, usize)>,
+}
+
+impl<V: ?Sized> Order<V> {
+    fn new<K>(eviction: &Eviction<K, V>) -> Self {
+        Self {
+            bounded: eviction.is_bounded(),
+            policy: eviction.policy,
+            live: Vec::new(),
+            hand: 0,
+            ranked: BTreeMap::new(),
+            deadlines: BTreeMap::new(),
+        }
+    }
+
+    fn is_ranked(&self) -> bool {
+        match self.policy {
+            EvictionPolicy::Lru
+            | EvictionPolicy::Lfu
+            | EvictionPolicy::Reject
+            | EvictionPolicy::TinyLfu => true,
+            EvictionPolicy::Clock | EvictionPolicy::SampledLru { .. } => false,
+        }
+    }
+
+    /// Links a slot boxed in the map.
+    fn link(&mut self, mut ptr: NonNull<Slot<V>>) {
+        let id = ptr.as_ptr() as usize;
+        // SAFETY: The slot is in the map, which we have &mut access to.
+        let slot = unsafe { ptr.as_mut() };
+        if !self.bounded && slot.deadline().is_none() {
+            return;
+        }
+        let rank = (self.bounded && self.is_ranked()).then(|| {
+            let (uses, last_used) = slot.usage(self.policy);
+            self.ranked.insert((uses, last_used, id), ptr);
+            (uses, last_used, id)
+        });
+        let deadline = slot.deadline().map(|deadline| {
+            self.deadlines.insert((deadline, id), ptr);
+            (deadline, id)
+        });
+        let pos = self.bounded.then(|| {
+            self.live.push(ptr);
+            self.live.len() - 1
+        });
+        slot.link = Some(Link {
+            pos,
+            rank,
+            deadline,
+        });
+    }
+
+    fn unlink(&mut self, slot: &mut Slot<V>) {
+        let Some(link) = slot.link.take() else {
+            return;
+        };
+        if let Some(pos) = link.pos {
+            self.live.swap_remove(pos);
+            if let Some(&moved) = self.live.get(pos) {
+                // SAFETY: Linked slots are in the map, which we have &mut
+                // access to, and this is not the slot being unlinked.
+                if let Some(moved) = unsafe { &mut *moved.as_ptr() }.link.as_mut() {
+                    moved.pos = Some(pos);
+                }
+            }
+        }
+        if let Some(rank) = link.rank {
+            self.ranked.remove(&rank);
+        }
+        if let Some(deadline) = link.deadline {
+            self.deadlines.remove(&deadline);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.live.clear();
+        self.hand = 0;
+        self.ranked.clear();
+        self.deadlines.clear();
+    }
+
+    /// A linked slot which has expired, if any.
+    fn expired(&mut self, now: Instant) -> Option<NonNull<Slot<V>>> {
+        loop {
+            let (&(queued, id), &ptr) = self.deadlines.first_key_value()?;
+            if queued > now {
+                return None;
+            }
+            // SAFETY: Linked slots are in the map, which we have &mut access to.
+            let slot = unsafe { &mut *ptr.as_ptr() };
+            let deadline = slot.deadline().unwrap_or(queued);
+            if deadline <= now {
+                return Some(ptr);
+            }
+            self.deadlines.remove(&(queued, id));
+            self.deadlines.insert((deadline, id), ptr);
+            if let Some(link) = &mut slot.link {
+                link.deadline = Some((deadline, id));
+            }
+        }
+    }
+
+    /// Advances the CLOCK hand past the next unreferenced slot, clearing the
+    /// reference bits it passes over, and returns it.
+    ///
+    /// Evicting the slot moves the last slot into its place, which the hand
+    /// then leaves for its next turn.
+    fn clock_hand(&mut self, now: Instant) -> Option<NonNull<Slot<V>>> {
+        // two turns, in case the first only clears reference bits
+        for _ in 0..2 * self.live.len() {
+            if self.hand >= self.live.len() {
+                self.hand = 0;
+            }
+            let ptr = self.live[self.hand];
+            self.hand += 1;
+            // SAFETY: Linked slots are in the map, which we have &mut access to.
+            let slot = unsafe { ptr.as_ref() };
+            if slot.is_expired(now) || !slot.referenced.swap(false, Ordering::Relaxed) {
+                return Some(ptr);
+            }
+        }
+        None
+    }
+
+    /// The linked slot to evict first: one which has expired, or else the
+    /// least recently (or for Lfu, frequently) used.
+    fn coldest(&mut self, now: Instant) -> Option<NonNull<Slot<V>>> {
+        if let Some(expired) = self.expired(now) {
+            return Some(expired);
+        }
+        loop {
+            let (&queued, &ptr) = self.ranked.first_key_value()?;
+            // SAFETY: Linked slots are in the map, which we have &mut access to.
+            let slot = unsafe { &mut *ptr.as_ptr() };
+            let (uses, last_used) = slot.usage(self.policy);
+            let rank = (uses, last_used, queued.2);
+            if rank <= queued {
+                return Some(ptr);
+            }
+            self.ranked.remove(&queued);
+            self.ranked.insert(rank, ptr);
+            if let Some(link) = &mut slot.link {
+                link.rank = Some(rank);
+            }
+        }
+    }
+}
+
+// SAFETY: The pointers are to slots the arena owns, and are only followed
+// with &mut access to it.
+unsafe impl<V: ?Sized> Send for Order<V> where Box<Slot<V>>: Send {}
+unsafe impl<V: ?Sized> Sync for Order<V> where Box<Slot<V>>: Sync {}
+
+#[derive(Debug)]
+struct Slot<V: ?Sized> {
+    /// `None` for a negative entry, recording that the key has no value.
+    value: Option<PinBox<V>>,
+    expires_at: Option<Instant>,
+    evicted: bool,
+    weight: usize,
+    last_used: AtomicU64,
+    uses: AtomicU64,
+    /// The CLOCK reference bit.
+    referenced: AtomicBool,
+    /// Whether revalidation has been asked for since the entry went stale.
+    revalidating: AtomicBool,
+    idle: Option<Idle>,
+    /// The hash of the key, if needed for TinyLFU admission.
+    hash: u64,
+    /// Where the slot is in its arena's `Order`, if linked.
+    link: Option<Link>,
+}
+
+/// The time-to-idle deadline of a slot, which moves on every access.
+#[derive(Debug)]
+struct Idle {
+    after: Duration,
+    since: Instant,
+    /// When the slot was last accessed, in nanoseconds since `since`.
+    accessed: AtomicU64,
+}
+
+impl Idle {
+    fn deadline(&self) -> Instant {
+        self.since + Duration::from_nanos(self.accessed.load(Ordering::Relaxed)) + self.after
+    }
+}
+
+impl Clone for Idle {
+    fn clone(&self) -> Self {
+        Self {
+            after: self.after,
+            since: self.since,
+            accessed: AtomicU64::new(self.accessed.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl<V: ?Sized> Slot<V> {
+    fn new(value: Option<Box<V>>, expires_at: Option<Instant>, tick: u64) -> Self {
+        Self {
+            value: value.map(PinBox::new),
+            expires_at,
+            evicted: false,
+            weight: 0,
+            last_used: AtomicU64::new(tick),
+            uses: AtomicU64::new(0),
+            referenced: AtomicBool::new(false),
+            revalidating: AtomicBool::new(false),
+            idle: None,
+            hash: 0,
+            link: None,
+        }
+    }
+
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.map_or(false, |t| t <= now)
+            || self
+                .idle
+                .as_ref()
+                .map_or(false, |idle| idle.deadline() <= now)
+    }
+
+    /// Whether the entry is logically present, even if negative.
+    fn is_live(&self, now: Instant) -> bool {
+        !self.evicted && !self.is_expired(now)
+    }
+
+    fn live(&self, now: Instant) -> Option<&PinBox<V>> {
+        self.value.as_ref().filter(|_| self.is_live(now))
+    }
+
+    /// When the slot expires, if ever. This only moves later.
+    fn deadline(&self) -> Option<Instant> {
+        let idle = self.idle.as_ref().map(Idle::deadline);
+        match (self.expires_at, idle) {
+            (Some(expires_at), Some(idle)) => Some(expires_at.min(idle)),
+            (expires_at, idle) => expires_at.or(idle),
+        }
+    }
+
+    /// How much the slot has been used, by uses for Lfu, then by recency.
+    /// This only grows.
+    fn usage(&self, policy: EvictionPolicy) -> (u64, u64) {
+        let last_used = self.last_used.load(Ordering::Relaxed);
+        let uses = match policy {
+            EvictionPolicy::Lfu => self.uses.load(Ordering::Relaxed),
+            EvictionPolicy::Lru
+            | EvictionPolicy::Clock
+            | EvictionPolicy::Reject
+            | EvictionPolicy::TinyLfu
+            | EvictionPolicy::SampledLru { .. } => 0,
+        };
+        (uses, last_used)
+    }
+
+    /// The order in which a Lru, Lfu, Reject, or sampled policy evicts slots,
+    /// lowest first: expired slots, then by usage.
+    fn rank(&self, policy: EvictionPolicy, now: Instant) -> (bool, u64, u64) {
+        let (uses, last_used) = self.usage(policy);
+        (!self.is_expired(now), uses, last_used)
+    }
+
+    /// Marks the slot as evicted, so that it is logically absent.
+    fn evict(&mut self, tally: &mut Tally) {
+        if !self.evicted {
+            self.evicted = true;
+            tally.evicted += 1;
+            tally.weight -= self.weight;
+        }
+    }
+}
+
+impl<V: Clone> Clone for Slot<V> {
+    fn clone(&self) -> Self {
+        Self {
+            value: self.value.clone(),
+            expires_at: self.expires_at,
+            evicted: self.evicted,
+            weight: self.weight,
+            last_used: AtomicU64::new(self.last_used.load(Ordering::Relaxed)),
+            uses: AtomicU64::new(self.uses.load(Ordering::Relaxed)),
+            referenced: AtomicBool::new(self.referenced.load(Ordering::Relaxed)),
+            revalidating: AtomicBool::new(self.revalidating.load(Ordering::Relaxed)),
+            idle: self.idle.clone(),
+            hash: self.hash,
+            // the clone is linked by the arena it goes in
+            link: None,
+        }
+    }
+}
+
+#[cfg(feature = "stats")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[cfg(feature = "stats")]
+impl CacheStats {
+    /// The fraction of lookups that were hits, or 0 if there were none.
+    pub fn hit_ratio(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+#[cfg(feature = "stats")]
+#[derive(Debug, Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[cfg(feature = "stats")]
+impl Clone for Counters {
+    fn clone(&self) -> Self {
+        Self {
+            hits: AtomicU64::new(self.hits.load(Ordering::Relaxed)),
+            misses: AtomicU64::new(self.misses.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl<K, V: ?Sized, S, F> Default for HashCache<K, V, S, F>
+where
+    S: Default + Clone,
+    F: Default,
+{
+    fn default() -> Self {
+        Self::with_config(HashCacheConfig::default())
+    }
+}
+
+impl<K, V: ?Sized> HashCache<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_config(HashCacheConfig {
+            capacity,
+            ..HashCacheConfig::default()
+        })
+    }
+}
+
+impl<K, V: ?Sized, S: Clone> HashCache<K, V, S> {
+    pub fn with_hasher(hasher: S) -> Self {
+        Self::with_config(HashCacheConfig::new(hasher, ()))
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hasher: S) -> Self {
+        Self::with_config(HashCacheConfig {
+            capacity,
+            ..HashCacheConfig::new(hasher, ())
+        })
+    }
+}
+
+impl PoisonPolicy {
+    fn recover<T>(self, result: LockResult<T>) -> T {
+        match (self, result) {
+            (_, Ok(guard)) => guard,
+            (PoisonPolicy::Ignore, Err(e)) => e.into_inner(),
+            (PoisonPolicy::Panic, Err(_)) => panic!("cache lock poisoned by a panic"),
+        }
+    }
+
+    fn read<T>(self, lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+        self.recover(lock.read())
+    }
+
+    fn try_read<T>(self, lock: &RwLock<T>) -> Option<RwLockReadGuard<'_, T>> {
+        match lock.try_read() {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::Poisoned(e)) => Some(self.recover(Err(e))),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+
+    fn write<T>(self, lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+        self.recover(lock.write())
+    }
+
+    fn get_mut<T>(self, lock: &mut RwLock<T>) -> &mut T {
+        self.recover(lock.get_mut())
+    }
+
+    fn into_inner<T>(self, lock: RwLock<T>) -> T {
+        self.recover(lock.into_inner())
+    }
+}
+
+fn shard_index<Q, S>(router: &S, shards: usize, key: &Q) -> usize
+where
+    Q: Hash + ?Sized,
+    S: BuildHasher,
+{
+    if shards == 1 {
+        return 0;
+    }
+    let mut hasher = router.build_hasher();
+    key.hash(&mut hasher);
+    // the shard maps use the same hasher, and take their bucket index from
+    // the low bits and their tag from the top 7, so route by the bits between
+    ((hasher.finish() >> 32) as usize) % shards
+}
+
+impl<K, V, S, G> HashCache<K, V, S, ProviderMut<G>> {
+    /// Like [`with_provider`](Self::with_provider), but for a [`ProviderMut`].
+    pub fn with_provider_mut(provider: G) -> Self
+    where
+        S: Default + Clone,
+        G: FnMut(&K) -> V,
+    {
+        Self::with_provider(ProviderMut::new(provider))
+    }
+}
+
+impl<K, V: ?Sized, S, F> HashCache<K, V, S, F> {
+    pub fn with_provider(provider: F) -> Self
+    where
+        S: Default + Clone,
+    {
+        Self::with_config(HashCacheConfig::new(S::default(), provider))
+    }
+
+    pub fn with_capacity_and_provider(capacity: usize, provider: F) -> Self
+    where
+        S: Default + Clone,
+    {
+        Self::with_config(HashCacheConfig {
+            capacity,
+            ..HashCacheConfig::new(S::default(), provider)
+        })
+    }
+
+    pub fn with_config(config: HashCacheConfig<K, V, S, F>) -> Self
+    where
+        S: Clone,
+    {
+        let HashCacheConfig {
+            capacity,
+            growth_factor,
+            hasher,
+            provider,
+            default_ttl,
+            time_to_idle,
+            max_entries,
+            high_watermark,
+            low_watermark,
+            eviction_policy,
+            weigher,
+            max_weight,
+            on_evict,
+            clock,
+            stale_while_revalidate,
+            on_stale,
+            writer,
+            reuse_allocations,
+            shards,
+            on_poison,
+        } = config;
+        let shards = shards.max(1);
+        let per_shard = |n: usize| (n + shards - 1) / shards;
+        let eviction = Eviction {
+            max_entries: max_entries.map(per_shard),
+            high_watermark: high_watermark.map(per_shard),
+            low_watermark: low_watermark.map(per_shard),
+            policy: eviction_policy,
+            weigher: weigher.map(Arc::from),
+            max_weight: max_weight.map(per_shard),
+            on_evict: on_evict.map(Arc::from),
+            clock: clock.map(Arc::from),
+            time_to_idle,
+            stale_for: stale_while_revalidate,
+            on_stale: on_stale.map(Arc::from),
+            writer: writer.map(Arc::from),
+            events: Mutex::new(None),
+            pool: reuse_allocations.then(Mutex::default),
+            growth_factor,
+        };
+        Self {
+            shards: (0..shards)
+                .map(|_| {
+                    let map =
+                        HashMap::with_capacity_and_hasher(per_shard(capacity), hasher.clone());
+                    RwLock::new(Arena::new(map, &eviction))
+                })
+                .collect(),
+            router: hasher,
+            provider,
+            default_ttl,
+            eviction,
+            flights: (0..shards).map(|_| Flights::new()).collect(),
+            poison: on_poison,
+            #[cfg(feature = "stats")]
+            stats: Counters::default(),
+        }
+    }
+
+    fn shard<Q>(&self, key: &Q) -> &RwLock<Arena<K, V, S>>
+    where
+        Q: Hash + ?Sized,
+        S: BuildHasher,
+    {
+        &self.shards[shard_index(&self.router, self.shards.len(), key)]
+    }
+
+    /// The keys in flight in the shard of `key`.
+    fn flights<Q>(&self, key: &Q) -> &Flights<K>
+    where
+        Q: Hash + ?Sized,
+        S: BuildHasher,
+    {
+        &self.flights[shard_index(&self.router, self.shards.len(), key)]
+    }
+
+    fn shards_mut(&mut self) -> impl Iterator<Item = &mut Arena<K, V, S>>
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        let eviction = &self.eviction;
+        let poison = self.poison;
+        // SAFETY: &mut self access invalidates all extant fn get(&self) -> &V.
+        self.shards.iter_mut().map(move |shard| {
+            let arena = poison.get_mut(shard);
+            arena.sweep(eviction);
+            arena
+        })
+    }
+
+    fn shard_mut<Q>(&mut self, key: &Q) -> &mut Arena<K, V, S>
+    where
+        K: Eq + Hash,
+        Q: Hash + ?Sized,
+        S: BuildHasher,
+    {
+        self.shards_mut().for_each(drop);
+        let index = shard_index(&self.router, self.shards.len(), key);
+        self.poison.get_mut(&mut self.shards[index])
+    }
+
+    fn read_all(&self) -> Vec<RwLockReadGuard<'_, Arena<K, V, S>>> {
+        self.shards
+            .iter()
+            .map(|shard| self.poison.read(shard))
+            .collect()
+    }
+
+    fn record_hit(&self) {
+        #[cfg(feature = "stats")]
+        self.stats.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_miss(&self) {
+        #[cfg(feature = "stats")]
+        self.stats.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A snapshot of the hit and miss counts since creation or the last
+    /// [`reset_stats`](Self::reset_stats).
+    ///
+    /// Every lookup counts exactly once: as a hit if the key was present, or
+    /// as a miss if it was not (including lookups that then run a provider).
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.stats.hits.load(Ordering::Relaxed),
+            misses: self.stats.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    #[cfg(feature = "stats")]
+    pub fn reset_stats(&self) {
+        self.stats.hits.store(0, Ordering::Relaxed);
+        self.stats.misses.store(0, Ordering::Relaxed);
+    }
+
+    fn into_maps(self) -> impl Iterator<Item = HashMap<K, PinBox<Slot<V>>, S>> {
+        let poison = self.poison;
+        self.shards
+            .into_vec()
+            .into_iter()
+            .map(move |shard| poison.into_inner(shard).map)
+    }
+
+    pub fn clear(&mut self) {
+        for shard in self.shards.iter_mut() {
+            self.poison.get_mut(shard).clear(&self.eviction);
+        }
+    }
+
+    pub fn retain<G>(&mut self, mut f: G)
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+        G: FnMut(&K, &V) -> bool,
+    {
+        for shard in self.shards.iter_mut() {
+            let arena = self.poison.get_mut(shard);
+            arena.sweep(&self.eviction);
+            let now = self.eviction.now();
+            arena.retain(&self.eviction, |k, slot| match slot.live(now) {
+                Some(v) => f(k, v),
+                None => slot.is_live(now),
+            });
+        }
+    }
+
+    /// Removes every entry for which `f` returns `true`, the inverse of
+    /// [`retain`](Self::retain), and returns how many were removed.
+    ///
+    /// ```
+    /// # use simple_cache::HashCache;
+    /// let mut cache: HashCache<(&str, u32), u32> = HashCache::new();
+    /// cache.insert(("a", 1), 1);
+    /// cache.insert(("a", 2), 2);
+    /// cache.insert(("b", 1), 3);
+    /// assert_eq!(cache.invalidate_matching(|&(tenant, _), _| tenant == "a"), 2);
+    /// assert_eq!(cache.len(), 1);
+    /// ```
+    pub fn invalidate_matching<G>(&mut self, mut f: G) -> usize
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+        G: FnMut(&K, &V) -> bool,
+    {
+        let mut removed = 0;
+        self.retain(|k, v| {
+            let matched = f(k, v);
+            removed += usize::from(matched);
+            !matched
+        });
+        removed
+    }
+
+    /// Reclaims the memory of every expired or evicted entry now, rather than
+    /// as a side effect of later `&mut self` methods, and returns how many
+    /// entries were reclaimed.
+    ///
+    /// Entries which can still be served while they are revalidated are kept.
+    pub fn purge(&mut self) -> usize
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        let now = self.eviction.now();
+        let mut purged = 0;
+        for shard in self.shards.iter_mut() {
+            let arena = self.poison.get_mut(shard);
+            purged += arena.retired.len();
+            arena.reclaim_retired(&self.eviction);
+            arena.trim(&self.eviction);
+            let len = arena.map.len();
+            arena.retain(&self.eviction, |_, slot| {
+                slot.is_live(now) || self.eviction.is_stale(slot, now)
+            });
+            purged += len - arena.map.len();
+        }
+        purged
+    }
+
+    /// The number of entries in the cache.
+    ///
+    /// This includes negative entries recorded by
+    /// [`get_or_insert_optional`](Self::get_or_insert_optional) and expired
+    /// entries still served while they are revalidated, but not other expired
+    /// or evicted entries, even before they are reclaimed.
+    pub fn len(&self) -> usize {
+        let now = self.eviction.now();
+        self.shards
+            .iter()
+            .map(|shard| self.poison.read(shard).live_len(&self.eviction, now))
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let now = self.eviction.now();
+        self.shards
+            .iter()
+            .all(|shard| self.poison.read(shard).live_len(&self.eviction, now) == 0)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| self.poison.read(shard).map.capacity())
+            .sum()
+    }
+
+    /// An estimate of the heap memory used by the cache, in bytes.
+    ///
+    /// This counts the map tables at their full capacity and the shallow
+    /// size of each boxed value, including those awaiting reclamation.
+    pub fn estimated_memory(&self) -> usize {
+        self.estimated_memory_with(|_| 0)
+    }
+
+    /// Like [`estimated_memory`](Self::estimated_memory), but adds the heap
+    /// memory owned by each value, as measured by `f`.
+    pub fn estimated_memory_with<G>(&self, f: G) -> usize
+    where
+        G: Fn(&V) -> usize,
+    {
+        let value = |value: &V| mem::size_of_val(value) + f(value);
+        self.shards
+            .iter()
+            .map(|shard| {
+                let arena = self.poison.read(shard);
+                let table = arena.map.capacity() * mem::size_of::<(K, PinBox<Slot<V>>)>()
+                    + arena.map.len() * mem::size_of::<Slot<V>>();
+                let values: usize = arena
+                    .map
+                    .values()
+                    .filter_map(|slot| slot.value.as_deref())
+                    .chain(arena.retired.iter().map(|(_, value)| &**value))
+                    .map(value)
+                    .sum();
+                table + values
+            })
+            .sum()
+    }
+
+    /// Locks the cache for iteration over its entries.
+    ///
+    /// Values borrow from `&self` as usual, but keys live inline in the map
+    /// and may move when it grows, so they borrow from the returned guard.
+    /// Inserting into the cache while the guard is held will deadlock.
+    ///
+    /// ```
+    /// # use simple_cache::HashCache;
+    /// let cache: HashCache<u32, u32> = HashCache::new();
+    /// for (k, v) in &cache.iter() {
+    ///     println!("{k}: {v}");
+    /// }
+    /// ```
+    pub fn iter(&self) -> Iter<'_, K, V, S> {
+        Iter {
+            shards: self.read_all(),
+            now: self.eviction.now(),
+        }
+    }
+
+    /// Locks the cache for iteration over its keys.
+    ///
+    /// As with [`iter`](Self::iter), keys borrow from the returned guard.
+    pub fn keys(&self) -> Keys<'_, K, V, S> {
+        Keys {
+            shards: self.read_all(),
+            now: self.eviction.now(),
+        }
+    }
+
+    /// Clones the live entries of the cache, in no particular order.
+    pub fn to_vec(&self) -> Vec<(K, V)>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        (&self.iter())
+            .into_iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    /// An iterator over the values of the cache.
+    ///
+    /// The iterator holds the read lock until it is dropped.
+    pub fn values(&self) -> Values<'_, K, V, S> {
+        let shards = self.read_all();
+        let iter = shards
+            .iter()
+            // SAFETY: The maps live in self.shards, not in the guards, and
+            // cannot be modified while the guards are held alongside the iterator.
+            .map(|arena| {
+                unsafe { &*(&**arena as *const Arena<K, V, S>) }
+                    .map
+                    .values()
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .flatten();
+        Values {
+            iter,
+            now: self.eviction.now(),
+            _shards: shards,
+        }
+    }
+}
+
+impl<K, V: ?Sized, S, F> HashCache<K, V, S, F>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let found = self.lookup(key);
+        match found {
+            Some(_) => self.record_hit(),
+            None => self.record_miss(),
+        }
+        found
+    }
+
+    /// Like [`get`](Self::get), for a cache which lives for the rest of the
+    /// program, such as a leaked one. The cache is never mutably borrowed
+    /// again, so its values are never freed, and live as long.
+    ///
+    /// ```
+    /// # use simple_cache::HashCache;
+    /// let cache: &'static HashCache<u32, String> = Box::leak(Box::new(HashCache::new()));
+    /// cache.get_or_insert_with(&1, |n| n.to_string());
+    /// let one: &'static str = cache.get_static(&1).unwrap();
+    /// assert_eq!(one, "1");
+    /// ```
+    pub fn get_static<Q>(&'static self, key: &Q) -> Option<&'static V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key)
+    }
+
+    /// Like [`get`](Self::get), but without counting as a use: the entry's
+    /// recency, use count, and idle timer are left as they were, and neither
+    /// TinyLFU admission nor the hit and miss counts see the lookup. So
+    /// peeking never changes which entry is evicted next, which suits
+    /// diagnostics and inspecting the cache from outside.
+    ///
+    /// ```
+    /// # use simple_cache::{EvictionPolicy, HashCache, HashCacheConfig};
+    /// let cache = HashCache::with_config(
+    ///     HashCacheConfig::builder()
+    ///         .max_entries(2)
+    ///         .eviction_policy(EvictionPolicy::Lru)
+    ///         .build(),
+    /// );
+    /// cache.get_or_insert_with(&1, |_| "one");
+    /// cache.get_or_insert_with(&2, |_| "two");
+    /// assert_eq!(cache.peek(&1), Some(&"one"));
+    /// // 1 is still the least recently used
+    /// cache.get_or_insert_with(&3, |_| "three");
+    /// assert_eq!(cache.peek(&1), None);
+    /// assert_eq!(cache.peek(&2), Some(&"two"));
+    /// ```
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let arena = &self.poison.read(self.shard(key));
+        let now = self.eviction.now();
+        let value = arena.map.get(key)?.live(now)?;
+        // SAFETY: The returned value lifetime is derived from &self.
+        Some(unsafe { value.as_ref() })
+    }
+
+    fn lookup<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.lookup_slot(key).flatten()
+    }
+
+    /// Like `lookup`, but `Some(None)` for a negative entry.
+    fn lookup_slot<Q>(&self, key: &Q) -> Option<Option<&V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let arena = &self.poison.read(self.shard(key));
+        let slot = arena.find(key, self.eviction.now())?;
+        // SAFETY: The returned value lifetime is derived from &self.
+        Some(slot.value.as_ref().map(|value| unsafe { value.as_ref() }))
+    }
+
+    /// Like `lookup_slot`, but for checking again after a miss.
+    fn recheck<Q>(&self, key: &Q) -> Option<Option<&V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let arena = &self.poison.read(self.shard(key));
+        let slot = arena.find_again(key, self.eviction.now())?;
+        // SAFETY: The returned value lifetime is derived from &self.
+        Some(slot.value.as_ref().map(|value| unsafe { value.as_ref() }))
+    }
+
+    /// Whether there is a live entry for `key`, negative or not, without
+    /// marking it as used.
+    fn holds<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let arena = &self.poison.read(self.shard(key));
+        let now = self.eviction.now();
+        arena
+            .map
+            .get(key)
+            .filter(|slot| slot.is_live(now))
+            .is_some()
+    }
+
+    /// Like `get`, but also returns a stale value, asking for it to be
+    /// revalidated the first time.
+    fn get_or_stale<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let found = self.lookup(key).or_else(|| self.lookup_stale(key));
+        match found {
+            Some(_) => self.record_hit(),
+            None => self.record_miss(),
+        }
+        found
+    }
+
+    fn lookup_stale<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let on_stale = self.eviction.on_stale.as_ref()?;
+        let now = self.eviction.now();
+        let (value, revalidate) = {
+            let arena = &self.poison.read(self.shard(key));
+            let slot = arena
+                .map
+                .get(key)
+                .filter(|slot| self.eviction.is_stale(slot, now))?;
+            let revalidate = !slot.revalidating.swap(true, Ordering::Relaxed);
+            // SAFETY: The returned value lifetime is derived from &self.
+            (unsafe { slot.value.as_ref()?.as_ref() }, revalidate)
+        };
+        if revalidate {
+            on_stale(key.to_owned());
+        }
+        Some(value)
+    }
+
+    /// Like [`get`](Self::get), but returns `None` instead of blocking if the
+    /// key's shard is locked for writing, as while a value is installed.
+    pub fn try_get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let found = self.poison.try_read(self.shard(key)).and_then(|arena| {
+            let value = arena.find(key, self.eviction.now())?.value.as_ref()?;
+            // SAFETY: The returned value lifetime is derived from &self.
+            Some(unsafe { value.as_ref() })
+        });
+        match found {
+            Some(_) => self.record_hit(),
+            None => self.record_miss(),
+        }
+        found
+    }
+
+    /// Looks up several keys at once, taking each shard's read lock only
+    /// once for the whole batch.
+    pub fn get_many<Q>(&self, keys: &[&Q]) -> Vec<Option<&V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let shards = self.read_all();
+        let now = self.eviction.now();
+        keys.iter()
+            .map(|&key| {
+                let arena = &shards[shard_index(&self.router, shards.len(), key)];
+                let found = arena.find(key, now).and_then(|slot| slot.value.as_ref());
+                match found {
+                    Some(_) => self.record_hit(),
+                    None => self.record_miss(),
+                }
+                // SAFETY: The returned value lifetime is derived from &self.
+                found.map(|value| unsafe { value.as_ref() })
+            })
+            .collect()
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let arena = &self.poison.read(self.shard(key));
+        let now = self.eviction.now();
+        arena.map.get(key).and_then(|slot| slot.live(now)).is_some()
+    }
+
+    /// How many times the entry for `key` has been looked up since it was
+    /// inserted, for finding unexpectedly hot or cold keys. Checking does not
+    /// count as a lookup.
+    ///
+    /// Uses are tracked for every entry, as the Lfu policy needs them.
+    pub fn access_count<Q>(&self, key: &Q) -> Option<u64>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let arena = &self.poison.read(self.shard(key));
+        let now = self.eviction.now();
+        let slot = arena.map.get(key).filter(|slot| slot.live(now).is_some())?;
+        Some(slot.uses.load(Ordering::Relaxed))
+    }
+
+    /// Builds a new cache of the live entries of this one, with each value
+    /// mapped by `f`.
+    ///
+    /// The new cache has the same hasher, shard count, and expiry settings,
+    /// and its entries keep their deadlines, but it has no provider, bounds,
+    /// or hooks.
+    pub fn map_values<V2, G>(&self, f: G) -> HashCache<K, V2, S, ()>
+    where
+        K: Clone,
+        S: Clone,
+        G: Fn(&V) -> V2,
+    {
+        let mut config = HashCacheConfig::new(self.router.clone(), ());
+        config.default_ttl = self.default_ttl;
+        config.time_to_idle = self.eviction.time_to_idle;
+        config.shards = self.shards.len();
+        config.on_poison = self.poison;
+        let mut cache = HashCache::with_config(config);
+        cache.eviction.clock = self.eviction.clock.clone();
+
+        let now = self.eviction.now();
+        for (shard, mapped) in self.shards.iter().zip(cache.shards.iter_mut()) {
+            let arena = self.poison.read(shard);
+            let mapped = cache.poison.get_mut(mapped);
+            mapped.map.reserve(arena.map.len());
+            for (k, slot) in &arena.map {
+                if let Some(v) = slot.live(now) {
+                    let mut new = Slot::new(Some(Box::new(f(v))), slot.expires_at, mapped.tick());
+                    new.idle = slot.idle.clone();
+                    mapped.install(k.clone(), new, &cache.eviction);
+                }
+            }
+        }
+        cache
+    }
+
+    /// Gets a mutable reference to the value of `key`.
+    ///
+    /// Like [`remove`](Self::remove), this requires `&mut self`, which
+    /// guarantees that no references previously handed out are still live.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = self.eviction.now();
+        let slot = self.shard_mut(key).map.get_mut(key)?;
+        if !slot.is_live(now) {
+            return None;
+        }
+        // SAFETY: &mut self access invalidates all extant fn get(&self) -> &V.
+        Some(unsafe { slot.value.as_mut()?.as_mut() })
+    }
+
+    /// Inserts a boxed value into the cache, replacing any existing entry.
+    ///
+    /// Unlike [`insert`](Self::insert), the value may be unsized, like `str`.
+    /// This requires `&mut self`, so the replaced value can be dropped
+    /// immediately, as no references to it can still be live.
+    pub fn insert_boxed(&mut self, key: K, value: Box<V>) -> &V {
+        let index = shard_index(&self.router, self.shards.len(), &key);
+        let arena = self.poison.get_mut(&mut self.shards[index]);
+        arena.sweep(&self.eviction);
+        self.eviction.write(&key, &value);
+        let slot = self
+            .eviction
+            .slot(Some(value), self.default_ttl, arena.tick());
+        let slot = arena.install(key, slot, &self.eviction);
+        // SAFETY: The returned value lifetime is derived from &mut self.
+        let value = unsafe { slot.value.as_ref().unwrap().as_ref() };
+        arena.reclaim_retired(&self.eviction);
+        value
+    }
+
+    /// Like [`get_or_insert_with`](Self::get_or_insert_with), but `f` boxes
+    /// the value itself, so it may be unsized, like `str`.
+    ///
+    /// ```
+    /// # use simple_cache::HashCache;
+    /// let cache: HashCache<u32, str> = HashCache::new();
+    /// let name = cache.get_or_insert_boxed(&1, |n| format!("#{n}").into());
+    /// assert_eq!(name, "#1");
+    /// ```
+    pub fn get_or_insert_boxed<Q, G>(&self, key: &Q, f: G) -> &V
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        G: FnOnce(&K) -> Box<V>,
+    {
+        if let Some(v) = self.get_or_stale(key) {
+            return v;
+        }
+
+        match self.try_insert_boxed_with(key, self.default_ttl, |key| Ok::<_, Infallible>(f(key))) {
+            Ok((v, _)) => v,
+            Err(never) => match never {},
+        }
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        let additional = (additional + self.shards.len() - 1) / self.shards.len();
+        for arena in self.shards_mut() {
+            arena.map.reserve(additional);
+        }
+    }
+
+    /// Shrinks the capacity of the backing map as much as possible.
+    ///
+    /// Values are boxed separately from the map's table, so their addresses
+    /// are unaffected by this or any other rehash.
+    pub fn shrink_to_fit(&mut self) {
+        for arena in self.shards_mut() {
+            arena.map.shrink_to_fit();
+        }
+    }
+
+    /// Shrinks the capacity of the backing map to no less than `min_capacity`.
+    ///
+    /// As with [`shrink_to_fit`](Self::shrink_to_fit), value addresses are
+    /// unaffected, as values are boxed separately from the map's table.
+    pub fn shrink_to(&mut self, min_capacity: usize) {
+        let min_capacity = (min_capacity + self.shards.len() - 1) / self.shards.len();
+        for arena in self.shards_mut() {
+            arena.map.shrink_to(min_capacity);
+        }
+    }
+
+    /// Concurrent misses on the same key are coalesced: one caller runs `f`
+    /// while the others wait for and then share its result. No shard lock is
+    /// held while `f` runs, only briefly afterwards to install the value.
+    ///
+    /// Also returns whether this call inserted the value.
+    fn try_insert_boxed_with<Q, G, E>(
+        &self,
+        key: &Q,
+        ttl: Option<Duration>,
+        f: G,
+    ) -> Result<(&V, bool), E>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        G: FnOnce(&K) -> Result<Box<V>, E>,
+    {
+        let leader = match self.flights(key).board(key, || self.recheck(key).flatten()) {
+            Ok(v) => return Ok((v, false)),
+            Err(gate) => gate.lead(key.to_owned()),
+        };
+        // on error, dropping the leader lets the next caller try instead
+        let value = f(leader.key())?;
+        self.eviction.write(leader.key(), &value);
+        Ok(leader.land(|key| self.install(key, value, ttl)))
+    }
+
+    /// Inserts `value` unless a live value for `key` is already present, and
+    /// returns whether it did.
+    fn install(&self, key: K, value: Box<V>, ttl: Option<Duration>) -> (&V, bool) {
+        let (v, inserted) = self.install_slot(key, Some(value), ttl);
+        let v = v.unwrap_or_else(|| unreachable!("a value replaces any negative entry"));
+        (v, inserted)
+    }
+
+    /// Inserts `value`, which is a negative entry if `None`, unless a live
+    /// value for `key` is already present. A value replaces a negative entry.
+    fn install_slot(
+        &self,
+        key: K,
+        value: Option<Box<V>>,
+        ttl: Option<Duration>,
+    ) -> (Option<&V>, bool) {
+        let arena = &mut *self.poison.write(self.shard(&key));
+        let now = self.eviction.now();
+        if let Some(slot) = arena.map.get(&key).filter(|slot| slot.is_live(now)) {
+            if slot.value.is_some() || value.is_none() {
+                // SAFETY: The returned value lifetime is derived from &self.
+                let v = slot.value.as_ref().map(|value| unsafe { value.as_ref() });
+                return (v, false);
+            }
+        }
+
+        let slot = self.eviction.slot(value, ttl, arena.tick());
+        let slot = arena.install(key, slot, &self.eviction);
+        // SAFETY: The returned value lifetime is derived from &self.
+        let v = slot.value.as_ref().map(|value| unsafe { value.as_ref() });
+        (v, true)
+    }
+}
+
+impl<K, V, S, F> HashCache<K, V, S, F>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    /// Inserts a value into the cache, replacing any existing entry.
+    ///
+    /// This requires `&mut self`, so the replaced value can be dropped
+    /// immediately, as no references to it can still be live.
+    pub fn insert(&mut self, key: K, value: V) -> &V {
+        self.insert_boxed(key, self.eviction.boxed(value))
+    }
+
+    /// Inserts a value into the cache unless the key is already present, in
+    /// which case the key and value are handed back.
+    ///
+    /// ```
+    /// # use simple_cache::HashCache;
+    /// let mut cache = HashCache::new();
+    /// assert_eq!(cache.try_insert(1, "a"), Ok(&"a"));
+    /// assert_eq!(cache.try_insert(1, "b"), Err((1, "b")));
+    /// ```
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<&V, (K, V)> {
+        match self.entry(key) {
+            Entry::Occupied(entry) => Err((entry.entry.key, value)),
+            Entry::Vacant(entry) => Ok(entry.insert(value)),
+        }
+    }
+
+    /// Inserts a value into the cache, returning the value it replaced, if
+    /// any live one.
+    ///
+    /// ```
+    /// # use simple_cache::HashCache;
+    /// let mut cache = HashCache::new();
+    /// assert_eq!(cache.replace(1, "a"), None);
+    /// assert_eq!(cache.replace(1, "b"), Some("a"));
+    /// assert_eq!(cache.get(&1), Some(&"b"));
+    /// ```
+    pub fn replace(&mut self, key: K, value: V) -> Option<V> {
+        match self.entry(key) {
+            Entry::Occupied(mut entry) => Some(entry.insert(value)),
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+                None
+            }
+        }
+    }
+
+    /// Gets the entry for `key` for in-place manipulation.
+    ///
+    /// As this borrows `&mut self`, the value references it produces are
+    /// stable for as long as that borrow lasts.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S> {
+        let index = shard_index(&self.router, self.shards.len(), &key);
+        let arena = self.poison.get_mut(&mut self.shards[index]);
+        arena.sweep(&self.eviction);
+        let now = self.eviction.now();
+        let occupied = arena
+            .map
+            .get(&key)
+            .and_then(|slot| slot.live(now))
+            .is_some();
+        let entry = EntryRef {
+            arena,
+            eviction: &self.eviction,
+            default_ttl: self.default_ttl,
+            key,
+        };
+        if occupied {
+            Entry::Occupied(OccupiedEntry { entry })
+        } else {
+            Entry::Vacant(VacantEntry { entry })
+        }
+    }
+
+    /// Returns the value for `key`, inserting the result of `f` if absent,
+    /// after evicting an arbitrary entry if the cache already holds
+    /// `max_entries`. The evicted entry is returned rather than dropped.
+    ///
+    /// This is a bounded cache without any eviction policy configured, which
+    /// takes `&mut self` so that the evicted value can be moved out.
+    ///
+    /// ```
+    /// # use simple_cache::HashCache;
+    /// let mut cache = HashCache::new();
+    /// assert_eq!(cache.get_or_insert_bounded(&1, 1, |_| "a"), (&"a", None));
+    /// assert_eq!(cache.get_or_insert_bounded(&1, 1, |_| "b"), (&"a", None));
+    /// assert_eq!(cache.get_or_insert_bounded(&2, 1, |_| "c"), (&"c", Some((1, "a"))));
+    /// ```
+    pub fn get_or_insert_bounded<Q, G>(
+        &mut self,
+        key: &Q,
+        max_entries: usize,
+        f: G,
+    ) -> (&V, Option<(K, V)>)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        G: FnOnce(&K) -> V,
+    {
+        if self.contains_key(key) {
+            return (self.get(key).unwrap(), None);
+        }
+        self.record_miss();
+
+        let now = self.eviction.now();
+        let mut evicted = None;
+        // reclaim what has already gone first, so only live entries count
+        self.shards_mut().for_each(drop);
+        if self.len() >= max_entries {
+            let victim = self.shards_mut().find_map(|arena| {
+                let (k, _) = arena
+                    .map
+                    .iter()
+                    .find(|(_, slot)| slot.live(now).is_some())?;
+                Some(Borrow::<Q>::borrow(k).to_owned())
+            });
+            if let Some(victim) = victim {
+                evicted = self.remove::<Q>(victim.borrow()).map(|v| (victim, v));
+            }
+        }
+        let key = key.to_owned();
+        let value = f(&key);
+        (self.insert(key, value), evicted)
+    }
+
+    /// Removes a key from the cache, returning the value if it was present.
+    ///
+    /// Like [`clear`](Self::clear), this requires `&mut self`, which
+    /// guarantees that no references previously handed out are still live.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = self.eviction.now();
+        let (key, slot) = self.shard_mut(key).remove(key)?;
+        let live = slot.is_live(now);
+        let value = slot.value?;
+        if !live {
+            self.eviction.reclaim(key, value);
+            return None;
+        }
+        Some(*value.into_box())
+    }
+
+    /// Removes every entry from the cache, returning the live ones.
+    ///
+    /// Unlike [`clear`](Self::clear), the values are handed back, such as to
+    /// flush them elsewhere. If the iterator is dropped early, the remaining
+    /// entries are dropped with it.
+    ///
+    /// ```
+    /// # use simple_cache::HashCache;
+    /// let mut cache = HashCache::new();
+    /// cache.insert(1, "one");
+    /// let drained: Vec<_> = cache.drain().collect();
+    /// assert_eq!(drained, [(1, "one")]);
+    /// assert!(cache.is_empty());
+    /// ```
+    pub fn drain(&mut self) -> impl Iterator<Item = (K, V)> + '_ {
+        let eviction = &self.eviction;
+        let poison = self.poison;
+        let now = eviction.now();
+        let drains: Vec<_> = self
+            .shards
+            .iter_mut()
+            .map(|shard| {
+                let arena = poison.get_mut(shard);
+                arena.tally = Tally::default();
+                arena.order.clear();
+                arena.reclaim_retired(eviction);
+                arena.map.drain()
+            })
+            .collect();
+        drains.into_iter().flatten().filter_map(move |(key, slot)| {
+            let slot = *slot.into_box();
+            let live = slot.is_live(now);
+            let value = slot.value?;
+            if !live {
+                eviction.reclaim(key, value);
+                return None;
+            }
+            Some((key, *value.into_box()))
+        })
+    }
+
+    /// Unwraps the cache into a map of its live entries.
+    pub fn into_inner(self) -> HashMap<K, V, S> {
+        let HashCache {
+            shards,
+            router,
+            eviction,
+            poison,
+            ..
+        } = self;
+        let now = eviction.now();
+        let mut map = HashMap::with_hasher(router);
+        for shard in shards.into_vec() {
+            let slots = poison.into_inner(shard).map;
+            map.reserve(slots.len());
+            map.extend(slots.into_iter().filter_map(|(k, slot)| {
+                let slot = *slot.into_box();
+                let live = slot.is_live(now);
+                let value = slot.value.filter(|_| live)?;
+                Some((k, *value.into_box()))
+            }));
+        }
+        map
+    }
+
+    /// Consumes the cache, returning its live values.
+    pub fn into_values(self) -> impl Iterator<Item = V> {
+        self.into_iter().map(|(_, v)| v)
+    }
+
+    /// Consumes the cache, returning the keys of its live entries.
+    pub fn into_keys(self) -> impl Iterator<Item = K> {
+        self.into_iter().map(|(k, _)| k)
+    }
+
+    /// Converts the cache into a read-only map of its live entries, for once
+    /// it is fully warmed, whose lookups take no lock.
+    ///
+    /// ```
+    /// # use simple_cache::HashCache;
+    /// let cache: HashCache<u32, u32> = HashCache::new();
+    /// cache.get_or_insert_with(&1, |&k| k * 10);
+    /// let frozen = cache.freeze();
+    /// assert_eq!(frozen.get(&1), Some(&10));
+    /// ```
+    pub fn freeze(self) -> FrozenCache<K, V, S> {
+        self.into()
+    }
+
+    /// Subscribes to the entries the cache reclaims, as would be passed to
+    /// the `on_evict` hook, which are sent to the returned receiver instead.
+    ///
+    /// The entries are moved into the channel, so need not be cloned. Sending
+    /// never blocks; if the receiver is dropped, entries go back to the hook
+    /// or are dropped. Subscribing again replaces the previous receiver.
+    ///
+    /// ```
+    /// # use simple_cache::{HashCache, HashCacheConfig};
+    /// let mut config = HashCacheConfig::default();
+    /// config.max_entries = Some(1);
+    /// let mut cache: HashCache<u32, u32> = HashCache::with_config(config);
+    /// let events = cache.eviction_events();
+    ///
+    /// cache.get_or_insert_with(&1, |_| 1);
+    /// cache.get_or_insert_with(&2, |_| 2);
+    /// cache.purge();
+    /// assert_eq!(events.try_recv(), Ok((1, 1)));
+    /// ```
+    pub fn eviction_events(&self) -> Receiver<(K, V)>
+    where
+        K: Send + 'static,
+        V: Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        let send = move |key, value: Box<V>| {
+            tx.send((key, *value))
+                .map_err(|SendError((key, value))| (key, Box::new(value)))
+        };
+        *self.eviction.events() = Some(Box::new(send));
+        rx
+    }
+
+    pub fn get_or_insert<Q>(&self, key: &Q) -> &V
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        F: Provider<K, V>,
+    {
+        if let Some(v) = self.get_or_stale(key) {
+            return v;
+        }
+
+        self.insert_with(key, self.default_ttl, |key| self.provider.provide(key))
+    }
+
+    /// Fills the cache ahead of use, running the provider for each of `keys`
+    /// which is not already present.
+    ///
+    /// Unlike lookups, warming does not count towards the hit and miss
+    /// statistics, nor as a use of entries already present.
+    pub fn warm<I>(&self, keys: I)
+    where
+        I: IntoIterator<Item = K>,
+        F: Provider<K, V>,
+    {
+        for key in keys {
+            if self.holds(&key) {
+                continue;
+            }
+            let leader = match self
+                .flights(&key)
+                .board(&key, || self.holds(&key).then_some(()))
+            {
+                Ok(_) => continue,
+                Err(gate) => gate.lead(key),
+            };
+            let value = self.eviction.boxed(self.provider.provide(leader.key()));
+            self.eviction.write(leader.key(), &value);
+            leader.land(|key| self.install(key, value, self.default_ttl));
+        }
+    }
+
+    /// Like [`warm`](Self::warm), but takes each shard's write lock only once
+    /// for all of its keys, to save handing the lock back and forth when many
+    /// keys are missing.
+    ///
+    /// The provider runs for the keys missing from each shard before its lock
+    /// is taken, so it may use the cache. Unlike `warm`, keys in flight on
+    /// other threads are not waited for, and a key given more than once may
+    /// be provided more than once; whichever value is installed first is
+    /// kept.
+    ///
+    /// ```
+    /// # use simple_cache::HashCache;
+    /// # use std::collections::hash_map::RandomState;
+    /// let cache: HashCache<u32, u32, RandomState, _> = HashCache::with_provider(|n: &u32| n * 2);
+    /// cache.get_or_insert_with(&1, |_| 1);
+    /// cache.extend_with_provider([1, 2, 3]);
+    /// assert_eq!(cache.get(&1), Some(&1));
+    /// assert_eq!(cache.get(&3), Some(&6));
+    /// ```
+    pub fn extend_with_provider<I>(&self, keys: I)
+    where
+        I: IntoIterator<Item = K>,
+        F: Provider<K, V>,
+    {
+        let mut by_shard: Vec<Vec<K>> = iter::repeat_with(Vec::new)
+            .take(self.shards.len())
+            .collect();
+        for key in keys {
+            by_shard[shard_index(&self.router, self.shards.len(), &key)].push(key);
+        }
+        for (shard, keys) in self.shards.iter().zip(by_shard) {
+            if keys.is_empty() {
+                continue;
+            }
+            let now = self.eviction.now();
+            let missing: Vec<K> = {
+                let arena = self.poison.read(shard);
+                keys.into_iter()
+                    .filter(|key| arena.map.get(key).and_then(|slot| slot.live(now)).is_none())
+                    .collect()
+            };
+            let provided: Vec<_> = missing
+                .into_iter()
+                .map(|key| {
+                    let value = self.eviction.boxed(self.provider.provide(&key));
+                    self.eviction.write(&key, &value);
+                    (key, value)
+                })
+                .collect();
+
+            let mut lost = Vec::new();
+            let mut arena = self.poison.write(shard);
+            let now = self.eviction.now();
+            for (key, value) in provided {
+                if arena
+                    .map
+                    .get(&key)
+                    .and_then(|slot| slot.live(now))
+                    .is_some()
+                {
+                    lost.push(value);
+                    continue;
+                }
+                let slot = self
+                    .eviction
+                    .slot(Some(value), self.default_ttl, arena.tick());
+                arena.install(key, slot, &self.eviction);
+            }
+            drop(arena);
+            lost.into_iter()
+                .for_each(|value| self.eviction.recycle(value));
+        }
+    }
+
+    /// Returns the value for `key`, inserting the result of `f` if absent.
+    ///
+    /// `f` runs without any lock held, so it may use the cache itself, e.g.
+    /// to memoize a recursive function:
+    ///
+    /// ```
+    /// # use simple_cache::HashCache;
+    /// fn fib(cache: &HashCache<u64, u64>, n: u64) -> u64 {
+    ///     *cache.get_or_insert_with(&n, |&n| match n {
+    ///         0 | 1 => n,
+    ///         n => fib(cache, n - 1) + fib(cache, n - 2),
+    ///     })
+    /// }
+    ///
+    /// let cache = HashCache::new();
+    /// assert_eq!(fib(&cache, 90), 2_880_067_194_370_816_120);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// If `f` recursively requests the same `key`, which could never finish.
+    ///
+    /// If `f` panics, the panic propagates without poisoning the cache and
+    /// nothing is inserted. The next call for `key`, including any that were
+    /// waiting on this one, runs its own `f` again:
+    ///
+    /// ```
+    /// # use simple_cache::HashCache;
+    /// # use std::panic::{catch_unwind, AssertUnwindSafe};
+    /// let cache = HashCache::new();
+    /// let result = catch_unwind(AssertUnwindSafe(|| {
+    ///     cache.get_or_insert_with("key", |_| panic!("failed to compute"));
+    /// }));
+    /// assert!(result.is_err());
+    /// assert!(!cache.contains_key("key"));
+    /// assert_eq!(cache.get_or_insert_with("key", |_| 42), &42);
+    /// ```
+    pub fn get_or_insert_with<Q, G>(&self, key: &Q, f: G) -> &V
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        G: FnOnce(&K) -> V,
+    {
+        self.get_or_insert_with_status(key, f).0
+    }
+
+    /// Like [`get_or_insert_with`](Self::get_or_insert_with), but passes
+    /// `ctx` to `f`, such as a connection scoped to the current request.
+    ///
+    /// `ctx` is dropped unused if the value is found.
+    pub fn get_or_insert_with_context<Q, C, G>(&self, key: &Q, ctx: C, f: G) -> &V
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        G: FnOnce(&K, C) -> V,
+    {
+        self.get_or_insert_with(key, |key| f(key, ctx))
+    }
+
+    /// Returns the value for `key`, inserting `V::default()` if absent.
+    pub fn get_or_default<Q>(&self, key: &Q) -> &V
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        V: Default,
+    {
+        self.get_or_insert_with(key, |_| V::default())
+    }
+
+    /// Like [`get_or_insert_with`](Self::get_or_insert_with), but also returns
+    /// whether this call inserted the value.
+    ///
+    /// When concurrent callers miss on the same key, only the one whose value
+    /// is inserted gets `true`; those which waited for it, or whose value lost
+    /// a race against another insertion, get `false`.
+    ///
+    /// ```
+    /// # use simple_cache::HashCache;
+    /// let cache = HashCache::new();
+    /// assert_eq!(cache.get_or_insert_with_status(&1, |_| 1), (&1, true));
+    /// assert_eq!(cache.get_or_insert_with_status(&1, |_| 2), (&1, false));
+    /// ```
+    pub fn get_or_insert_with_status<Q, G>(&self, key: &Q, f: G) -> (&V, bool)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        G: FnOnce(&K) -> V,
+    {
+        if let Some(v) = self.get_or_stale(key) {
+            return (v, false);
+        }
+
+        self.insert_with_status(key, self.default_ttl, f)
+    }
+
+    /// Ensures `key` is present, inserting the result of `f` if absent, and
+    /// returns whether it was already present. Unlike
+    /// [`get_or_insert_with_status`](Self::get_or_insert_with_status), the
+    /// result does not borrow the cache.
+    ///
+    /// ```
+    /// # use simple_cache::HashCache;
+    /// let mut cache = HashCache::new();
+    /// assert!(!cache.contains_or_insert(&1, |_| 1));
+    /// assert!(cache.contains_or_insert(&1, |_| 2));
+    /// cache.clear();
+    /// ```
+    pub fn contains_or_insert<Q, G>(&self, key: &Q, f: G) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        G: FnOnce(&K) -> V,
+    {
+        let (_, inserted) = self.get_or_insert_with_status(key, f);
+        !inserted
+    }
+
+    /// Computes a new value for `key` with the provider, unless it has a live
+    /// value already, such as once it has gone stale.
+    ///
+    /// Concurrent calls are coalesced as for [`get_or_insert`](Self::get_or_insert).
+    pub fn revalidate<Q>(&self, key: &Q) -> &V
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        F: Provider<K, V>,
+    {
+        self.insert_with(key, self.default_ttl, |key| self.provider.provide(key))
+    }
+
+    /// Like [`revalidate`](Self::revalidate), but computes the value with `f`.
+    pub fn revalidate_with<Q, G>(&self, key: &Q, f: G) -> &V
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        G: FnOnce(&K) -> V,
+    {
+        self.insert_with(key, self.default_ttl, f)
+    }
+
+    /// Like [`get_or_insert_with`](Self::get_or_insert_with), but a value
+    /// which `is_stale` says is out of date, such as after a version bump, is
+    /// replaced by the result of `f` as well.
+    ///
+    /// The replaced value may still be borrowed, so it is only dropped by the
+    /// next `&mut self` method. If another thread replaces it first, the other
+    /// thread's value is returned instead.
+    ///
+    /// ```
+    /// # use simple_cache::HashCache;
+    /// let cache = HashCache::new();
+    /// let old = cache.get_or_refresh("config", |_| false, |_| (1, "old"));
+    /// let new = cache.get_or_refresh("config", |&(v, _)| v < 2, |_| (2, "new"));
+    /// assert_eq!(old, &(1, "old"));
+    /// assert_eq!(new, &(2, "new"));
+    /// assert_eq!(cache.get("config"), Some(&(2, "new")));
+    /// ```
+    pub fn get_or_refresh<Q, P, G>(&self, key: &Q, is_stale: P, f: G) -> &V
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        P: FnOnce(&V) -> bool,
+        G: FnOnce(&K) -> V,
+    {
+        let stale = match self.get(key) {
+            Some(v) if is_stale(v) => v,
+            Some(v) => return v,
+            None => return self.insert_with(key, self.default_ttl, f),
+        };
+        let shard = self.shard(key);
+        let key = key.to_owned();
+        let value = self.eviction.boxed(f(&key));
+        self.eviction.write(&key, &value);
+
+        let arena = &mut *self.poison.write(shard);
+        let now = self.eviction.now();
+        let current = arena.map.get::<K>(&key).and_then(|slot| slot.live(now));
+        if let Some(current) = current.filter(|current| !ptr::eq(&***current, stale)) {
+            // SAFETY: The returned value lifetime is derived from &self.
+            return unsafe { current.as_ref() };
+        }
+        let slot = self
+            .eviction
+            .slot(Some(value), self.default_ttl, arena.tick());
+        let slot = arena.install(key, slot, &self.eviction);
+        let value = slot.value.as_ref().unwrap_or_else(|| unreachable!());
+        // SAFETY: The returned value lifetime is derived from &self.
+        unsafe { value.as_ref() }
+    }
+
+    /// Like [`get_or_insert_with`](Self::get_or_insert_with), but a newly
+    /// inserted entry expires after `ttl` instead of the configured default.
+    ///
+    /// Expired entries are treated as absent, but references to them stay
+    /// valid; the memory is reclaimed by the next `&mut self` method.
+    pub fn get_or_insert_with_ttl<Q, G>(&self, key: &Q, ttl: Duration, f: G) -> &V
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        G: FnOnce(&K) -> V,
+    {
+        if let Some(v) = self.get_or_stale(key) {
+            return v;
+        }
+
+        self.insert_with(key, Some(ttl), f)
+    }
+
+    /// Like [`get_or_insert_with`](Self::get_or_insert_with), but takes an
+    /// owned key, which is only moved into the cache if it was absent.
+    pub fn get_or_insert_owned<G>(&self, key: K, f: G) -> &V
+    where
+        G: FnOnce(&K) -> V,
+    {
+        if let Some(v) = self.get(&key) {
+            return v;
+        }
+
+        let leader = match self
+            .flights(&key)
+            .board(&key, || self.recheck(&key).flatten())
+        {
+            Ok(v) => return v,
+            Err(gate) => gate.lead(key),
+        };
+        let value = self.eviction.boxed(f(leader.key()));
+        self.eviction.write(leader.key(), &value);
+        leader.land(|key| self.install(key, value, self.default_ttl).0)
+    }
+
+    /// Like [`get_or_insert_owned`](Self::get_or_insert_owned), but for an
+    /// initializer that can fail, as with
+    /// [`get_or_try_insert`](Self::get_or_try_insert).
+    ///
+    /// ```
+    /// # use simple_cache::HashCache;
+    /// let cache = HashCache::new();
+    /// let parse = |s: &String| s.parse::<u32>();
+    /// assert!(cache.get_or_insert_fallible_owned("x".to_string(), parse).is_err());
+    /// assert_eq!(cache.get_or_insert_fallible_owned("1".to_string(), parse), Ok(&1));
+    /// assert!(!cache.contains_key("x"));
+    /// ```
+    pub fn get_or_insert_fallible_owned<G, E>(&self, key: K, f: G) -> Result<&V, E>
+    where
+        G: FnOnce(&K) -> Result<V, E>,
+    {
+        if let Some(v) = self.get(&key) {
+            return Ok(v);
+        }
+
+        let leader = match self
+            .flights(&key)
+            .board(&key, || self.recheck(&key).flatten())
+        {
+            Ok(v) => return Ok(v),
+            Err(gate) => gate.lead(key),
+        };
+        // on error, dropping the leader lets the next caller try instead
+        let value = self.eviction.boxed(f(leader.key())?);
+        self.eviction.write(leader.key(), &value);
+        Ok(leader.land(|key| self.install(key, value, self.default_ttl).0))
+    }
+
+    /// Like [`get_many`](Self::get_many), but runs `f` to insert the value of
+    /// each key that is missing.
+    ///
+    /// The hits are found under a single read lock per shard, then each miss
+    /// is inserted as by [`get_or_insert_with`](Self::get_or_insert_with).
+    pub fn get_many_or_insert_with<Q, G>(&self, keys: &[&Q], mut f: G) -> Vec<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        G: FnMut(&K) -> V,
+    {
+        let found = self.get_many(keys);
+        keys.iter()
+            .zip(found)
+            .map(|(&key, found)| {
+                found.unwrap_or_else(|| self.insert_with(key, self.default_ttl, &mut f))
+            })
+            .collect()
+    }
+
+    /// Like [`get_or_insert_with`](Self::get_or_insert_with), but for an
+    /// initializer that can fail.
+    ///
+    /// If `f` returns an error, nothing is inserted and the error is returned;
+    /// the next call for `key` runs its own `f` again.
+    pub fn get_or_try_insert<Q, G, E>(&self, key: &Q, f: G) -> Result<&V, E>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        G: FnOnce(&K) -> Result<V, E>,
+    {
+        if let Some(v) = self.get_or_stale(key) {
+            return Ok(v);
+        }
+
+        self.try_insert_with(key, self.default_ttl, f)
+            .map(|(v, _)| v)
+    }
+
+    /// Like [`get_or_insert_with`](Self::get_or_insert_with), but `f` may
+    /// find that `key` has no value.
+    ///
+    /// A `None` result is cached as a negative entry, so later calls return
+    /// `None` without running `f` until it expires or is evicted. Negative
+    /// entries count towards [`len`](Self::len) and `max_entries`, but weigh
+    /// nothing. Inserting a value for the key replaces its negative entry.
+    pub fn get_or_insert_optional<Q, G>(&self, key: &Q, f: G) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        G: FnOnce(&K) -> Option<V>,
+    {
+        let found = self.lookup_slot(key);
+        match found {
+            Some(_) => self.record_hit(),
+            None => self.record_miss(),
+        }
+        if let Some(found) = found {
+            return found;
+        }
+
+        let leader = match self.flights(key).board(key, || self.recheck(key)) {
+            Ok(found) => return found,
+            Err(gate) => gate.lead(key.to_owned()),
+        };
+        let value = f(leader.key());
+        if let Some(value) = &value {
+            self.eviction.write(leader.key(), value);
+        }
+        leader.land(|key| {
+            self.install_slot(key, value.map(|v| self.eviction.boxed(v)), self.default_ttl)
+                .0
+        })
+    }
+
+    fn insert_with<Q, G>(&self, key: &Q, ttl: Option<Duration>, f: G) -> &V
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        G: FnOnce(&K) -> V,
+    {
+        self.insert_with_status(key, ttl, f).0
+    }
+
+    fn insert_with_status<Q, G>(&self, key: &Q, ttl: Option<Duration>, f: G) -> (&V, bool)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        G: FnOnce(&K) -> V,
+    {
+        match self.try_insert_with(key, ttl, |key| Ok::<_, Infallible>(f(key))) {
+            Ok(found) => found,
+            Err(never) => match never {},
+        }
+    }
+
+    fn try_insert_with<Q, G, E>(
+        &self,
+        key: &Q,
+        ttl: Option<Duration>,
+        f: G,
+    ) -> Result<(&V, bool), E>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        G: FnOnce(&K) -> Result<V, E>,
+    {
+        self.try_insert_boxed_with(key, ttl, |key| f(key).map(|v| self.eviction.boxed(v)))
+    }
+
+    /// Returns the value for `key`, computing it with the provider if absent,
+    /// as [`get_or_compute_with`](Self::get_or_compute_with) does.
+    pub fn get_or_compute<Q>(&self, key: &Q) -> MaybeCached<'_, V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        F: Provider<K, V>,
+    {
+        self.get_or_compute_with(key, |key| self.provider.provide(key))
+    }
+
+    /// Like [`get_or_insert_with`](Self::get_or_insert_with), but if the
+    /// eviction policy is [`Reject`](EvictionPolicy::Reject) and the cache is
+    /// full, the value is returned without being cached.
+    ///
+    /// Concurrent misses are coalesced as for `get_or_insert_with`, but each
+    /// caller waiting on a value which was not cached then computes its own.
+    ///
+    /// ```
+    /// # use simple_cache::{EvictionPolicy, HashCache, HashCacheConfig, MaybeCached};
+    /// let config = HashCacheConfig::builder()
+    ///     .max_entries(1)
+    ///     .eviction_policy(EvictionPolicy::Reject)
+    ///     .build();
+    /// let cache: HashCache<u32, u32> = HashCache::with_config(config);
+    ///
+    /// assert!(matches!(cache.get_or_compute_with(&1, |_| 1), MaybeCached::Borrowed(&1)));
+    /// assert!(matches!(cache.get_or_compute_with(&2, |_| 2), MaybeCached::Owned(2)));
+    /// assert_eq!(cache.get(&1), Some(&1));
+    /// assert_eq!(cache.get(&2), None);
+    /// ```
+    pub fn get_or_compute_with<Q, G>(&self, key: &Q, f: G) -> MaybeCached<'_, V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        G: FnOnce(&K) -> V,
+    {
+        if let Some(v) = self.get_or_stale(key) {
+            return MaybeCached::Borrowed(v);
+        }
+
+        let leader = match self.flights(key).board(key, || self.recheck(key).flatten()) {
+            Ok(v) => return MaybeCached::Borrowed(v),
+            Err(gate) => gate.lead(key.to_owned()),
+        };
+        let value = f(leader.key());
+        self.eviction.write(leader.key(), &value);
+        leader.land(|key| self.install_or_reject(key, value))
+    }
+
+    /// Returns the value for `key` if it is cached, or else the result of
+    /// `f`, which is not cached, so that the caller can decide whether to
+    /// [`insert`](Self::insert) it later.
+    ///
+    /// Concurrent misses are not coalesced; each runs its own `f`.
+    ///
+    /// ```
+    /// # use simple_cache::{HashCache, MaybeCached};
+    /// let cache = HashCache::new();
+    /// cache.get_or_insert_with(&1, |_| 1);
+    /// assert!(matches!(cache.get_or_compute_uncached(&1, |_| 10), MaybeCached::Borrowed(&1)));
+    /// assert!(matches!(cache.get_or_compute_uncached(&2, |_| 20), MaybeCached::Owned(20)));
+    /// assert_eq!(cache.get(&2), None);
+    /// ```
+    pub fn get_or_compute_uncached<Q, G>(&self, key: &Q, f: G) -> MaybeCached<'_, V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        G: FnOnce(&K) -> V,
+    {
+        match self.get(key) {
+            Some(v) => MaybeCached::Borrowed(v),
+            None => MaybeCached::Owned(f(&key.to_owned())),
+        }
+    }
+
+    /// Like `install`, but hands `value` back instead of evicting a live
+    /// entry when the eviction policy is `Reject`.
+    fn install_or_reject(&self, key: K, value: V) -> MaybeCached<'_, V> {
+        let arena = &mut *self.poison.write(self.shard(&key));
+        let now = self.eviction.now();
+        if let Some(v) = arena.map.get(&key).and_then(|slot| slot.live(now)) {
+            // SAFETY: The returned value lifetime is derived from &self.
+            return MaybeCached::Borrowed(unsafe { v.as_ref() });
+        }
+        if self.eviction.policy == EvictionPolicy::Reject {
+            let weight = self.eviction.weigh(&key, &value);
+            if !arena.make_room_expired(&self.eviction, weight) {
+                return MaybeCached::Owned(value);
+            }
+        }
+
+        let slot = self.eviction.slot(
+            Some(self.eviction.boxed(value)),
+            self.default_ttl,
+            arena.tick(),
+        );
+        let slot = arena.install(key, slot, &self.eviction);
+        // SAFETY: The returned value lifetime is derived from &self.
+        MaybeCached::Borrowed(unsafe { slot.value.as_ref().unwrap().as_ref() })
+    }
+
+    /// Like [`get_or_insert_with`](Self::get_or_insert_with), but for an
+    /// asynchronous initializer. Works with any executor.
+    ///
+    /// Concurrent misses on the same key are coalesced, waiting without
+    /// blocking the thread, and no lock is held while the future runs. If
+    /// the future is dropped before it finishes, the next caller for `key`
+    /// runs its own `f` instead.
+    pub async fn get_or_insert_async<Q, G, Fut>(&self, key: &Q, f: G) -> &V
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        G: FnOnce(&K) -> Fut,
+        Fut: Future<Output = V>,
+    {
+        let f = |key: &K| {
+            let value = f(key);
+            async { Ok::<_, Infallible>(value.await) }
+        };
+        match self.get_or_try_insert_async(key, f).await {
+            Ok(v) => v,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Like [`get_or_insert_async`](Self::get_or_insert_async), but for an
+    /// initializer that can fail.
+    ///
+    /// If the future resolves to an error, nothing is inserted and the error
+    /// is returned; the next caller for `key`, including any that were
+    /// waiting on this one, runs its own `f` again.
+    pub async fn get_or_try_insert_async<Q, G, Fut, E>(&self, key: &Q, f: G) -> Result<&V, E>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        G: FnOnce(&K) -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+    {
+        if let Some(v) = self.get_or_stale(key) {
+            return Ok(v);
+        }
+
+        let leader = match self
+            .flights(key)
+            .board_async(key, || self.recheck(key).flatten())
+            .await
+        {
+            Ok(v) => return Ok(v),
+            Err(leader) => leader,
+        };
+        let value = self.eviction.boxed(f(leader.key()).await?);
+        self.eviction.write(leader.key(), &value);
+        Ok(leader.land(|key| self.install(key, value, self.default_ttl).0))
+    }
 }
 
-#[non_exhaustive]
-#[derive(Debug, Default)]
-pub struct HashCacheConfig<S, F> {
-    pub capacity: usize,
-    pub hasher: S,
-    pub provider: F,
+/// Shows the number of entries and capacity, and with `{:#?}`, the entries.
+impl<K, V: ?Sized, S, F> fmt::Debug for HashCache<K, V, S, F>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let alternate = f.alternate();
+        let mut s = f.debug_struct("HashCache");
+        s.field("len", &self.len())
+            .field("capacity", &self.capacity());
+        if alternate {
+            s.field("entries", &self.iter());
+        }
+        s.finish()
+    }
 }
 
-impl<K, V, S, F> Default for HashCache<K, V, S, F>
+impl<K, V, S, F> Clone for HashCache<K, V, S, F>
 where
-    S: Default,
-    F: Default,
+    K: Clone,
+    V: Clone,
+    S: Clone,
+    F: Clone,
 {
-    fn default() -> Self {
-        Self::with_config(HashCacheConfig::default())
+    fn clone(&self) -> Self {
+        Self {
+            shards: self
+                .shards
+                .iter()
+                .map(|shard| RwLock::new(self.poison.read(shard).duplicate(&self.eviction)))
+                .collect(),
+            router: self.router.clone(),
+            provider: self.provider.clone(),
+            default_ttl: self.default_ttl,
+            eviction: self.eviction.clone(),
+            flights: self.shards.iter().map(|_| Flights::new()).collect(),
+            poison: self.poison,
+            #[cfg(feature = "stats")]
+            stats: self.stats.clone(),
+        }
     }
 }
 
-impl<K, V> HashCache<K, V> {
-    pub fn new() -> Self {
-        Self::default()
+impl<K, Q, V, S, F> Index<&Q> for HashCache<K, V, S, F>
+where
+    K: Eq + Hash + Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+    V: ?Sized,
+    S: BuildHasher,
+{
+    type Output = V;
+
+    /// # Panics
+    ///
+    /// If the key is not present in the cache.
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("no entry found for key")
     }
+}
 
-    pub fn with_capacity(capacity: usize) -> Self {
-        Self::with_config(HashCacheConfig {
-            capacity,
-            hasher: RandomState::default(),
-            provider: (),
-        })
+impl<K, V, S> From<HashMap<K, V, S>> for HashCache<K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher + Clone,
+{
+    fn from(map: HashMap<K, V, S>) -> Self {
+        let mut config = HashCacheConfig::new(map.hasher().clone(), ());
+        config.capacity = map.len();
+        let mut cache = Self::with_config(config);
+        cache.extend(map);
+        cache
     }
 }
 
-impl<K, V, S> HashCache<K, V, S> {
-    pub fn with_hasher(hasher: S) -> Self {
-        Self::with_config(HashCacheConfig {
-            capacity: 0,
-            hasher,
-            provider: (),
-        })
+impl<K, V> FromIterator<(K, V)> for HashCache<K, V>
+where
+    K: Eq + Hash,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut cache = Self::new();
+        cache.extend(iter);
+        cache
     }
 }
 
-impl<K, V, S, F> HashCache<K, V, S, F> {
-    pub fn with_provider(provider: F) -> Self
-    where
-        S: Default,
-    {
-        Self::with_config(HashCacheConfig {
-            capacity: 0,
-            hasher: S::default(),
-            provider,
-        })
+impl<K, V, S, F> Extend<(K, V)> for HashCache<K, V, S, F>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+        // reserving swept every shard
+        for (k, v) in iter {
+            let index = shard_index(&self.router, self.shards.len(), &k);
+            let arena = self.poison.get_mut(&mut self.shards[index]);
+            self.eviction.write(&k, &v);
+            let slot =
+                self.eviction
+                    .slot(Some(self.eviction.boxed(v)), self.default_ttl, arena.tick());
+            arena.install(k, slot, &self.eviction);
+            // nothing can borrow an overwritten value under &mut self
+            arena.reclaim_retired(&self.eviction);
+        }
     }
+}
 
-    pub fn with_config(config: HashCacheConfig<S, F>) -> Self {
-        let HashCacheConfig {
-            capacity,
-            hasher,
-            provider,
-        } = config;
-        Self {
-            arena: RwLock::new(HashMap::with_capacity_and_hasher(capacity, hasher)),
-            provider,
+impl<K, V, S, F> IntoIterator for HashCache<K, V, S, F> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let now = self.eviction.now();
+        IntoIter {
+            iter: self
+                .into_maps()
+                .map(HashMap::into_iter)
+                .collect::<Vec<_>>()
+                .into_iter()
+                .flatten(),
+            now,
         }
     }
+}
 
-    fn arena(&self) -> RwLockReadGuard<HashMap<K, PinBox<V>, S>> {
-        // just ignore poisoning
-        self.arena.read().unwrap_or_else(|e| e.into_inner())
+/// A value from [`HashCache::get_or_compute_with`], which is either in the
+/// cache, or was computed but not cached because the cache is full, or from
+/// [`HashCache::get_or_compute_uncached`], which never caches what it
+/// computes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MaybeCached<'a, V> {
+    /// The value in the cache.
+    Borrowed(&'a V),
+    /// A value which was not cached.
+    Owned(V),
+}
+
+impl<V> MaybeCached<'_, V> {
+    pub fn is_cached(&self) -> bool {
+        matches!(self, MaybeCached::Borrowed(_))
     }
 
-    fn arena_mut(&self) -> RwLockWriteGuard<HashMap<K, PinBox<V>, S>> {
-        // just ignore poisoning
-        self.arena.write().unwrap_or_else(|e| e.into_inner())
+    pub fn into_owned(self) -> V
+    where
+        V: Clone,
+    {
+        match self {
+            MaybeCached::Borrowed(v) => v.clone(),
+            MaybeCached::Owned(v) => v,
+        }
     }
+}
 
-    pub fn clear(&mut self) {
-        // SAFETY: &mut self access invalidates all extant fn get(&self) -> &V.
-        // just ignore poisoning
-        self.arena
-            .get_mut()
-            .unwrap_or_else(|e| e.into_inner())
-            .clear();
+impl<V> Deref for MaybeCached<'_, V> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        match self {
+            MaybeCached::Borrowed(v) => v,
+            MaybeCached::Owned(v) => v,
+        }
     }
 }
 
-impl<K, V, S, F> HashCache<K, V, S, F>
+/// A view into a single entry of a [`HashCache`], from [`HashCache::entry`].
+#[derive(Debug)]
+pub enum Entry<'a, K, V, S = RandomState> {
+    Occupied(OccupiedEntry<'a, K, V, S>),
+    Vacant(VacantEntry<'a, K, V, S>),
+}
+
+/// An entry of a [`HashCache`] with a live value.
+#[derive(Debug)]
+pub struct OccupiedEntry<'a, K, V, S = RandomState> {
+    entry: EntryRef<'a, K, V, S>,
+}
+
+/// An entry of a [`HashCache`] without a live value.
+#[derive(Debug)]
+pub struct VacantEntry<'a, K, V, S = RandomState> {
+    entry: EntryRef<'a, K, V, S>,
+}
+
+#[derive(Debug)]
+struct EntryRef<'a, K, V, S> {
+    arena: &'a mut Arena<K, V, S>,
+    eviction: &'a Eviction<K, V>,
+    default_ttl: Option<Duration>,
+    key: K,
+}
+
+impl<'a, K, V, S> Entry<'a, K, V, S>
 where
     K: Eq + Hash,
     S: BuildHasher,
 {
-    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    pub fn or_insert(self, value: V) -> &'a V {
+        self.or_insert_with(|| value)
+    }
+
+    pub fn or_insert_with<G>(self, f: G) -> &'a V
     where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
+        G: FnOnce() -> V,
     {
-        let arena = &self.arena();
-        let value = arena.get(key)?;
-        // SAFETY: The returned value lifetime is derived from &self.
-        Some(unsafe { value.as_ref() })
+        match self {
+            Entry::Occupied(entry) => entry.into_ref(),
+            Entry::Vacant(entry) => entry.insert(f()),
+        }
     }
 
-    pub fn get_or_insert<Q>(&self, key: &Q) -> &V
+    /// Replaces the value if the entry is occupied, reclaiming the old value
+    /// like any other replaced entry, so that it is passed to `on_evict` with
+    /// a clone of the key.
+    pub fn and_replace(self, value: V) -> Self
     where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
-        F: Fn(&K) -> V,
+        K: Clone,
     {
-        if let Some(v) = self.get(key) {
-            return v;
+        match self {
+            Entry::Occupied(mut entry) => {
+                let old = entry.replace(value);
+                entry.entry.eviction.reclaim(entry.key().clone(), old);
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
         }
+    }
+}
 
-        let key = key.to_owned();
-        let arena = &mut self.arena_mut();
-        let value = arena.entry(key).or_insert_with_key(|k| {
-            let v = (self.provider)(k);
-            PinBox::new(Box::new(v))
-        });
-        // SAFETY: The returned value lifetime is derived from &self.
+impl<'a, K, V, S> OccupiedEntry<'a, K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    pub fn key(&self) -> &K {
+        &self.entry.key
+    }
+
+    fn slot(&self) -> &Slot<V> {
+        &self.entry.arena.map[&self.entry.key]
+    }
+
+    pub fn get(&self) -> &V {
+        self.slot().value.as_deref().unwrap()
+    }
+
+    pub fn into_ref(self) -> &'a V {
+        let value = self.slot().value.as_ref().unwrap();
+        // SAFETY: The value lifetime is derived from the &'a mut HashCache.
         unsafe { value.as_ref() }
     }
 
-    pub fn get_or_insert_with<Q, G>(&self, key: &Q, f: G) -> &V
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
-        G: FnOnce(&K) -> V,
-    {
-        if let Some(v) = self.get(key) {
-            return v;
+    /// Replaces the value of the entry, returning the old value.
+    ///
+    /// The entry is inserted anew, with a fresh TTL, as if by
+    /// [`HashCache::insert`].
+    pub fn insert(&mut self, value: V) -> V {
+        *self.replace(value).into_box()
+    }
+
+    fn replace(&mut self, value: V) -> PinBox<V> {
+        let EntryRef {
+            arena,
+            eviction,
+            default_ttl,
+            key,
+        } = &mut self.entry;
+        eviction.write(key, &value);
+        let (old_key, old) = arena.remove(key).unwrap();
+        let slot = eviction.slot(Some(eviction.boxed(value)), *default_ttl, arena.tick());
+        arena.install(old_key, slot, eviction);
+        old.value.unwrap()
+    }
+}
+
+impl<'a, K, V, S> VacantEntry<'a, K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    pub fn key(&self) -> &K {
+        &self.entry.key
+    }
+
+    pub fn insert(self, value: V) -> &'a V {
+        let EntryRef {
+            arena,
+            eviction,
+            default_ttl,
+            key,
+        } = self.entry;
+        eviction.write(&key, &value);
+        let slot = eviction.slot(Some(eviction.boxed(value)), default_ttl, arena.tick());
+        let slot = arena.install(key, slot, eviction);
+        // SAFETY: The value lifetime is derived from the &'a mut HashCache.
+        unsafe { slot.value.as_ref().unwrap().as_ref() }
+    }
+}
+
+/// An owning iterator over the entries of a [`HashCache`].
+pub struct IntoIter<K, V> {
+    iter: iter::Flatten<vec::IntoIter<hash_map::IntoIter<K, PinBox<Slot<V>>>>>,
+    now: Instant,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (k, slot) = self.iter.next()?;
+            let slot = *slot.into_box();
+            if let (true, Some(v)) = (slot.is_live(self.now), slot.value) {
+                return Some((k, *v.into_box()));
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+/// A read lock over a [`HashCache`], iterable by reference.
+pub struct Iter<'a, K, V: ?Sized, S = RandomState> {
+    shards: Vec<RwLockReadGuard<'a, Arena<K, V, S>>>,
+    now: Instant,
+}
+
+impl<'a, 'b, K, V: ?Sized, S> IntoIterator for &'b Iter<'a, K, V, S> {
+    type Item = (&'b K, &'a V);
+    type IntoIter = Entries<'a, 'b, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Entries {
+            iter: self
+                .shards
+                .iter()
+                .map(|arena| arena.map.iter())
+                .collect::<Vec<_>>()
+                .into_iter()
+                .flatten(),
+            now: self.now,
+            _marker: PhantomData,
         }
+    }
+}
 
-        let key = key.to_owned();
-        let arena = &mut self.arena_mut();
-        let value = arena.entry(key).or_insert_with_key(|k| {
-            let v = f(k);
-            PinBox::new(Box::new(v))
-        });
-        // SAFETY: The returned value lifetime is derived from &self.
-        unsafe { value.as_ref() }
+impl<K, V: ?Sized, S> fmt::Debug for Iter<'_, K, V, S>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self).finish()
+    }
+}
+
+/// An iterator over the entries of a [`HashCache`], created by [`Iter`].
+pub struct Entries<'a, 'b, K, V: ?Sized> {
+    iter: iter::Flatten<vec::IntoIter<hash_map::Iter<'b, K, PinBox<Slot<V>>>>>,
+    now: Instant,
+    _marker: PhantomData<&'a V>,
+}
+
+impl<'a, 'b, K, V: ?Sized> Iterator for Entries<'a, 'b, K, V> {
+    type Item = (&'b K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (k, slot) = self.iter.next()?;
+            if let Some(v) = slot.live(self.now) {
+                // SAFETY: The value lifetime is derived from the &'a HashCache.
+                return Some((k, unsafe { v.as_ref() }));
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+/// A read lock over a [`HashCache`], iterable by reference over its keys.
+pub struct Keys<'a, K, V: ?Sized, S = RandomState> {
+    shards: Vec<RwLockReadGuard<'a, Arena<K, V, S>>>,
+    now: Instant,
+}
+
+impl<'a, 'b, K, V: ?Sized, S> IntoIterator for &'b Keys<'a, K, V, S> {
+    type Item = &'b K;
+    type IntoIter = KeysIter<'b, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        KeysIter {
+            iter: self
+                .shards
+                .iter()
+                .map(|arena| arena.map.iter())
+                .collect::<Vec<_>>()
+                .into_iter()
+                .flatten(),
+            now: self.now,
+        }
+    }
+}
+
+/// An iterator over the keys of a [`HashCache`], created by [`Keys`].
+pub struct KeysIter<'b, K, V: ?Sized> {
+    iter: iter::Flatten<vec::IntoIter<hash_map::Iter<'b, K, PinBox<Slot<V>>>>>,
+    now: Instant,
+}
+
+impl<'b, K, V: ?Sized> Iterator for KeysIter<'b, K, V> {
+    type Item = &'b K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (k, slot) = self.iter.next()?;
+            if slot.live(self.now).is_some() {
+                return Some(k);
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+/// An iterator over the values of a [`HashCache`].
+pub struct Values<'a, K, V: ?Sized, S = RandomState> {
+    iter: iter::Flatten<vec::IntoIter<hash_map::Values<'a, K, PinBox<Slot<V>>>>>,
+    now: Instant,
+    _shards: Vec<RwLockReadGuard<'a, Arena<K, V, S>>>,
+}
+
+impl<'a, K, V: ?Sized, S> Iterator for Values<'a, K, V, S> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(v) = self.iter.next()?.live(self.now) {
+                // SAFETY: The value lifetime is derived from the &'a HashCache.
+                return Some(unsafe { v.as_ref() });
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
     }
 }
 
@@ -169,6 +4035,9 @@ struct PinBox<T: ?Sized> {
 }
 
 impl<T: ?Sized> PinBox<T> {
+    /// A box of a zero-sized value owns no allocation, just a dangling but
+    /// aligned pointer, which `Box::from_raw` accepts back, so zero-sized
+    /// values cost nothing here.
     fn new(x: Box<T>) -> Self {
         Self {
             ptr: NonNull::new(Box::into_raw(x)).unwrap(),
@@ -176,14 +4045,38 @@ impl<T: ?Sized> PinBox<T> {
         }
     }
 
+    fn into_box(self) -> Box<T> {
+        let this = ManuallyDrop::new(self);
+        unsafe { Box::from_raw(this.ptr.as_ptr()) }
+    }
+
     unsafe fn as_ref<'a>(&self) -> &'a T {
         self.ptr.as_ref()
     }
+
+    /// The pointer to the value, which can be used for as long as the box is
+    /// alive, alongside borrows through the box itself.
+    fn ptr(&self) -> NonNull<T> {
+        self.ptr
+    }
+
+    /// Only sound to call where no shared reference to the value can be
+    /// live, i.e. with `&mut` access to the whole cache.
+    unsafe fn as_mut(&mut self) -> &mut T {
+        self.ptr.as_mut()
+    }
 }
 
 impl<T: ?Sized> Drop for PinBox<T> {
     fn drop(&mut self) {
-        unsafe { Box::from_raw(self.ptr.as_ptr()) };
+        drop(unsafe { Box::from_raw(self.ptr.as_ptr()) });
+    }
+}
+
+impl<T: Clone> Clone for PinBox<T> {
+    fn clone(&self) -> Self {
+        // a fresh allocation, so the clone shares no pointee with self
+        PinBox::new(Box::new((**self).clone()))
     }
 }
 
@@ -194,6 +4087,17 @@ impl<T: ?Sized> Deref for PinBox<T> {
     }
 }
 
+/// Slots, unlike values, are never borrowed for longer than their box is, so
+/// the box can hand out &mut access to them.
+impl<V: ?Sized> DerefMut for PinBox<Slot<V>> {
+    fn deref_mut(&mut self) -> &mut Slot<V> {
+        // SAFETY: &mut self excludes any borrow through the box, and slot
+        // pointers elsewhere are only followed with &mut access to the arena
+        // owning the box.
+        unsafe { self.as_mut() }
+    }
+}
+
 impl<T: ?Sized + fmt::Debug> fmt::Debug for PinBox<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         (**self).fmt(f)
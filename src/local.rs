@@ -0,0 +1,230 @@
+use std::{
+    any::Any,
+    borrow::Borrow,
+    cell::RefCell,
+    collections::{hash_map::RandomState, HashMap},
+    fmt,
+    hash::{BuildHasher, Hash},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use crate::{HashCache, PinBox, Provider};
+
+thread_local! {
+    static CACHES: RefCell<Caches> = RefCell::new(Caches::default());
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// How many `ThreadLocalCache`s have been dropped, so that threads know when
+/// to look for caches they no longer need.
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// This thread's caches.
+#[derive(Default)]
+struct Caches {
+    /// The cache for each `ThreadLocalCache`, by id, with whether the
+    /// `ThreadLocalCache` is still alive.
+    by_id: HashMap<usize, (Arc<AtomicBool>, PinBox<dyn Any>)>,
+    /// The value of `DROPPED` when this thread last pruned its caches.
+    pruned_at: usize,
+}
+
+impl Caches {
+    /// Takes out the caches of dropped `ThreadLocalCache`s, for the caller to
+    /// drop once the caches are no longer borrowed, in case a value uses a
+    /// cache.
+    fn prune(&mut self) -> Vec<PinBox<dyn Any>> {
+        let dropped = DROPPED.load(Ordering::Acquire);
+        if dropped == self.pruned_at {
+            return Vec::new();
+        }
+        self.pruned_at = dropped;
+        let dead: Vec<usize> = self
+            .by_id
+            .iter()
+            .filter(|(_, (alive, _))| !alive.load(Ordering::Acquire))
+            .map(|(&id, _)| id)
+            .collect();
+        dead.into_iter()
+            .filter_map(|id| self.by_id.remove(&id))
+            .map(|(_, cache)| cache)
+            .collect()
+    }
+}
+
+/// A cache with a separate [`HashCache`] for each thread, so that it can
+/// hold values which are not `Send` or `Sync`, like `Rc`, while itself being
+/// shared between threads, such as in a `static`.
+///
+/// Each thread's cache is dropped when the thread exits, or once the
+/// `ThreadLocalCache` is dropped: right away for the thread which drops it,
+/// and for other threads the next time they use any `ThreadLocalCache`, so
+/// that long-lived threads such as in a pool do not keep them. Values never
+/// leave their thread, so lookups return clones. To borrow values, use
+/// [`with`](Self::with).
+///
+/// ```
+/// # use simple_cache::ThreadLocalCache;
+/// # use std::rc::Rc;
+/// let cache: ThreadLocalCache<u32, Rc<str>> = ThreadLocalCache::new();
+/// let a = cache.get_or_insert_with(&1, |n| n.to_string().into());
+/// let b = cache.get_or_insert_with(&1, |_| unreachable!());
+/// assert!(Rc::ptr_eq(&a, &b));
+///
+/// std::thread::scope(|s| {
+///     s.spawn(|| assert!(cache.with(|cache| cache.is_empty())));
+/// });
+/// ```
+pub struct ThreadLocalCache<K, V, S = RandomState, F = ()> {
+    id: usize,
+    /// Shared with each thread's cache, to tell once this is dropped, even if
+    /// the thread never uses it again.
+    alive: Arc<AtomicBool>,
+    /// Values are only held by the thread-local caches, so this is all that
+    /// is shared between threads.
+    init: Box<dyn Fn() -> HashCache<K, V, S, F> + Send + Sync>,
+}
+
+impl<K, V, S, F> Default for ThreadLocalCache<K, V, S, F>
+where
+    K: 'static,
+    V: 'static,
+    S: Default + Clone + 'static,
+    F: Default + 'static,
+{
+    fn default() -> Self {
+        Self::with_init(HashCache::default)
+    }
+}
+
+impl<K: 'static, V: 'static> ThreadLocalCache<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<K, V, S, F> ThreadLocalCache<K, V, S, F>
+where
+    K: 'static,
+    V: 'static,
+    S: 'static,
+    F: 'static,
+{
+    /// A cache which makes the cache for each thread with `init`, the first
+    /// time that thread uses it.
+    pub fn with_init(init: impl Fn() -> HashCache<K, V, S, F> + Send + Sync + 'static) -> Self {
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            alive: Arc::new(AtomicBool::new(true)),
+            init: Box::new(init),
+        }
+    }
+
+    /// Runs `f` with this thread's cache.
+    ///
+    /// # Panics
+    ///
+    /// If called while this thread's thread-local storage is being destroyed.
+    pub fn with<R>(&self, f: impl FnOnce(&HashCache<K, V, S, F>) -> R) -> R {
+        let (found, pruned) = CACHES.with(|caches| {
+            let mut caches = caches.borrow_mut();
+            let pruned = caches.prune();
+            (
+                caches.by_id.get(&self.id).map(|(_, cache)| cache.ptr),
+                pruned,
+            )
+        });
+        // dropped outside the borrow, in case a value uses a cache
+        drop(pruned);
+        let cache = match found {
+            Some(cache) => cache,
+            None => {
+                // no borrow is held while `init` runs, in case it uses a cache
+                let cache = PinBox::new(Box::new((self.init)()) as Box<dyn Any>);
+                let ptr = cache.ptr;
+                let alive = Arc::clone(&self.alive);
+                // replaced only if `init` used this cache, so is unborrowed
+                let replaced = CACHES.with(|caches| {
+                    let mut caches = caches.borrow_mut();
+                    caches.by_id.insert(self.id, (alive, cache))
+                });
+                drop(replaced);
+                ptr
+            }
+        };
+        // SAFETY: The cache is only dropped by this thread, on exit, or by
+        // dropping or pruning it once self is dropped, none of which can
+        // happen during `f`.
+        let cache = unsafe { cache.as_ref() };
+        f(cache.downcast_ref().unwrap())
+    }
+
+    pub fn len(&self) -> usize {
+        self.with(HashCache::len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.with(HashCache::is_empty)
+    }
+}
+
+impl<K, V, S, F> ThreadLocalCache<K, V, S, F>
+where
+    K: Eq + Hash + 'static,
+    V: Clone + 'static,
+    S: BuildHasher + 'static,
+    F: 'static,
+{
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.with(|cache| cache.get(key).cloned())
+    }
+
+    pub fn get_or_insert<Q>(&self, key: &Q) -> V
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        F: Provider<K, V>,
+    {
+        self.with(|cache| cache.get_or_insert(key).clone())
+    }
+
+    pub fn get_or_insert_with<Q, G>(&self, key: &Q, f: G) -> V
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        G: FnOnce(&K) -> V,
+    {
+        self.with(|cache| cache.get_or_insert_with(key, f).clone())
+    }
+}
+
+impl<K, V, S, F> Drop for ThreadLocalCache<K, V, S, F> {
+    fn drop(&mut self) {
+        // other threads prune theirs the next time they use any
+        // ThreadLocalCache, or else drop them when they exit
+        self.alive.store(false, Ordering::Release);
+        DROPPED.fetch_add(1, Ordering::Release);
+        let cache = CACHES
+            .try_with(|caches| caches.borrow_mut().by_id.remove(&self.id))
+            .ok()
+            .flatten();
+        // dropped outside the borrow, in case a value uses a cache
+        drop(cache);
+    }
+}
+
+impl<K, V, S, F> fmt::Debug for ThreadLocalCache<K, V, S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ThreadLocalCache")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
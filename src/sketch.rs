@@ -0,0 +1,75 @@
+//! A count-min sketch estimating how often keys are seen, for TinyLFU
+//! admission.
+
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+/// The number of rows, each indexed by a differently mixed hash.
+const DEPTH: usize = 4;
+const SEEDS: [u64; DEPTH] = [
+    0x9e37_79b9_7f4a_7c15,
+    0xc2b2_ae3d_27d4_eb4f,
+    0x1656_67b1_9e37_79f9,
+    0xd6e8_feb8_6659_fd93,
+];
+/// The most a counter holds, as in TinyLFU's 4-bit counters.
+const MAX_COUNT: u8 = 15;
+
+#[derive(Debug)]
+pub(crate) struct Sketch {
+    counters: Box<[AtomicU8]>,
+    width: usize,
+    /// Increments since the counters were last halved.
+    additions: AtomicUsize,
+    /// How many increments there are between halvings.
+    period: usize,
+}
+
+impl Sketch {
+    /// A sketch for a cache holding up to `capacity` entries.
+    pub(crate) fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(16);
+        // a few counters per entry keeps collisions with popular keys rare
+        let width = (capacity * 8).next_power_of_two();
+        Self {
+            counters: (0..DEPTH * width).map(|_| AtomicU8::new(0)).collect(),
+            width,
+            additions: AtomicUsize::new(0),
+            period: 10 * capacity,
+        }
+    }
+
+    fn counters(&self, hash: u64) -> impl Iterator<Item = &AtomicU8> {
+        SEEDS.iter().enumerate().map(move |(row, &seed)| {
+            let mixed = (hash ^ seed).wrapping_mul(seed);
+            let column = (mixed >> 32) as usize & (self.width - 1);
+            &self.counters[row * self.width + column]
+        })
+    }
+
+    /// Records a sighting of the key with the given hash.
+    ///
+    /// Every so often, all counts are halved, so that the sketch follows
+    /// changes in which keys are popular.
+    pub(crate) fn increment(&self, hash: u64) {
+        for counter in self.counters(hash) {
+            let _ = counter.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+                (n < MAX_COUNT).then_some(n + 1)
+            });
+        }
+        if self.additions.fetch_add(1, Ordering::Relaxed) + 1 >= self.period {
+            self.additions.store(0, Ordering::Relaxed);
+            for counter in self.counters.iter() {
+                let _ = counter.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| Some(n / 2));
+            }
+        }
+    }
+
+    /// An estimate of how often the key with the given hash has been seen,
+    /// which may be too high but never too low, besides aging.
+    pub(crate) fn estimate(&self, hash: u64) -> u8 {
+        self.counters(hash)
+            .map(|counter| counter.load(Ordering::Relaxed))
+            .min()
+            .unwrap_or(0)
+    }
+}
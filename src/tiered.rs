@@ -0,0 +1,338 @@
+use std::{
+    any::Any,
+    borrow::Borrow,
+    cell::RefCell,
+    collections::{hash_map::RandomState, HashMap},
+    fmt,
+    hash::{BuildHasher, Hash},
+    mem,
+    ptr::NonNull,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use crate::{HashCache, Provider};
+
+thread_local! {
+    static TIERS: RefCell<Tiers> = RefCell::new(Tiers::default());
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// How many `TieredCache`s have been dropped, so that threads know when to
+/// look for L1s they no longer need.
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// This thread's L1s.
+#[derive(Default)]
+struct Tiers {
+    /// The L1 for each `TieredCache`, by id, with whether the cache is still
+    /// alive.
+    by_id: HashMap<usize, (Arc<AtomicBool>, Box<dyn Any>)>,
+    /// The value of `DROPPED` when this thread last pruned its L1s.
+    pruned_at: usize,
+}
+
+impl Tiers {
+    /// Takes out the L1s of caches which have been dropped, for the caller to
+    /// drop once the tiers are no longer borrowed, in case a key uses a cache.
+    fn prune(&mut self) -> Vec<Box<dyn Any>> {
+        let dropped = DROPPED.load(Ordering::Acquire);
+        if dropped == self.pruned_at {
+            return Vec::new();
+        }
+        self.pruned_at = dropped;
+        let dead: Vec<usize> = self
+            .by_id
+            .iter()
+            .filter(|(_, (alive, _))| !alive.load(Ordering::Acquire))
+            .map(|(&id, _)| id)
+            .collect();
+        dead.into_iter()
+            .filter_map(|id| self.by_id.remove(&id))
+            .map(|(_, tier)| tier)
+            .collect()
+    }
+}
+
+/// A shared [`HashCache`], the L2, with a small per-thread L1 in front of it,
+/// so that lookups of a thread's hot keys take no lock.
+///
+/// L1 holds references to values in L2, and values found in L2 are promoted
+/// into the looking thread's L1, which evicts its least recently promoted or
+/// found entries to stay within its bound.
+///
+/// Values in L2 are only freed through `&mut self`, so every `&mut self`
+/// method invalidates every thread's L1. Through `&self`, L2 can only evict,
+/// expire, or retire values, which stay alive until then, so L1 may keep
+/// returning a value L2 no longer holds, until L1 evicts it in turn.
+///
+/// ```
+/// # use simple_cache::TieredCache;
+/// let mut cache = TieredCache::new(16);
+/// let a = cache.get_or_insert_with("a", |k| k.len());
+/// assert_eq!(cache.get("a"), Some(a));
+///
+/// cache.clear();
+/// assert_eq!(cache.get("a"), None);
+/// ```
+pub struct TieredCache<K, V, S = RandomState, F = ()> {
+    id: usize,
+    /// Shared with each thread's L1, to tell once the cache is dropped, even if
+    /// the thread never uses it again.
+    alive: Arc<AtomicBool>,
+    /// Bumped by every method which needs `&mut self`, as it may free values
+    /// which L1s still point to.
+    epoch: u64,
+    l1_entries: usize,
+    l2: HashCache<K, V, S, F>,
+}
+
+/// One thread's L1 for a `TieredCache`.
+struct Tier<K, V> {
+    /// SAFETY: pointers are only followed while the epoch matches the cache's.
+    epoch: u64,
+    map: HashMap<K, (NonNull<V>, u64)>,
+    /// The source of recency stamps for eviction.
+    tick: u64,
+}
+
+impl<K: 'static, V: 'static> TieredCache<K, V> {
+    /// A cache whose L1 holds up to `l1_entries` entries on each thread.
+    pub fn new(l1_entries: usize) -> Self {
+        Self::with_l2(HashCache::new(), l1_entries)
+    }
+}
+
+impl<K, V, S, F> TieredCache<K, V, S, F>
+where
+    K: 'static,
+    V: 'static,
+{
+    /// A cache in front of `l2`, whose L1 holds up to `l1_entries` entries on
+    /// each thread.
+    pub fn with_l2(l2: HashCache<K, V, S, F>, l1_entries: usize) -> Self {
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            alive: Arc::new(AtomicBool::new(true)),
+            epoch: 0,
+            l1_entries,
+            l2,
+        }
+    }
+
+    pub fn l2(&self) -> &HashCache<K, V, S, F> {
+        &self.l2
+    }
+
+    /// The L2, invalidating every thread's L1.
+    pub fn l2_mut(&mut self) -> &mut HashCache<K, V, S, F> {
+        self.epoch += 1;
+        &mut self.l2
+    }
+
+    pub fn clear(&mut self) {
+        self.l2_mut().clear();
+    }
+
+    /// Runs `f` with this thread's L1, if it has one, emptying it first if it
+    /// is no longer valid. Also prunes the L1s of dropped caches.
+    ///
+    /// Keys dropped along the way are only dropped once `TIERS` is no longer
+    /// borrowed, in case a key uses a cache, so `f` should hand back any it
+    /// takes out of the L1.
+    fn with_tier<R>(&self, f: impl FnOnce(&mut Tier<K, V>) -> R) -> Option<R> {
+        let (found, garbage) = TIERS
+            .try_with(|tiers| {
+                let mut tiers = tiers.borrow_mut();
+                let pruned = tiers.prune();
+                let tier = tiers
+                    .by_id
+                    .get_mut(&self.id)
+                    .and_then(|(_, tier)| tier.downcast_mut::<Tier<K, V>>());
+                let Some(tier) = tier else {
+                    return (None, (pruned, None));
+                };
+                let mut invalid = None;
+                if tier.epoch != self.epoch {
+                    tier.epoch = self.epoch;
+                    invalid = Some(mem::take(&mut tier.map));
+                }
+                (Some(f(tier)), (pruned, invalid))
+            })
+            .ok()?;
+        drop(garbage);
+        found
+    }
+}
+
+impl<K, V, S, F> TieredCache<K, V, S, F>
+where
+    K: Eq + Hash + 'static,
+    V: 'static,
+    S: BuildHasher + 'static,
+    F: 'static,
+{
+    fn l1<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let found = self.with_tier(|tier| {
+            tier.tick += 1;
+            let (value, used) = tier.map.get_mut(key)?;
+            *used = tier.tick;
+            Some(*value)
+        });
+        // SAFETY: The value is in L2, which has not been mutably borrowed
+        // since, as that would have changed the epoch.
+        found.flatten().map(|value| unsafe { value.as_ref() })
+    }
+
+    /// Puts a value from L2 into this thread's L1.
+    fn promote<'a, Q>(&'a self, key: &Q, value: &'a V) -> &'a V
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        if self.l1_entries == 0 {
+            return value;
+        }
+        // cloned and dropped outside the borrow of TIERS, in case a key uses
+        // a cache
+        let mut key = Some(key.to_owned());
+        let promoted =
+            self.with_tier(|tier| tier.insert(key.take().unwrap(), value, self.l1_entries));
+        match promoted {
+            Some(unused) => drop(unused),
+            None => {
+                let mut tier = Tier {
+                    epoch: self.epoch,
+                    map: HashMap::new(),
+                    tick: 0,
+                };
+                tier.insert(key.take().unwrap(), value, self.l1_entries);
+                let alive = Arc::clone(&self.alive);
+                let replaced = TIERS
+                    .try_with(|tiers| {
+                        let mut tiers = tiers.borrow_mut();
+                        tiers.by_id.insert(self.id, (alive, Box::new(tier)))
+                    })
+                    .ok()
+                    .flatten();
+                // replaced only if a stale tier failed to downcast, which
+                // cannot happen as ids are unique, but is dropped outside the
+                // borrow
+                drop(replaced);
+            }
+        }
+        value
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        if let Some(value) = self.l1(key) {
+            return Some(value);
+        }
+        let value = self.l2.get(key)?;
+        Some(self.promote(key, value))
+    }
+
+    pub fn get_or_insert<Q>(&self, key: &Q) -> &V
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        F: Provider<K, V>,
+    {
+        if let Some(value) = self.l1(key) {
+            return value;
+        }
+        self.promote(key, self.l2.get_or_insert(key))
+    }
+
+    pub fn get_or_insert_with<Q, G>(&self, key: &Q, f: G) -> &V
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        G: FnOnce(&K) -> V,
+    {
+        if let Some(value) = self.l1(key) {
+            return value;
+        }
+        self.promote(key, self.l2.get_or_insert_with(key, f))
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> &V {
+        self.l2_mut().insert(key, value)
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.l2_mut().remove(key)
+    }
+}
+
+impl<K: Eq + Hash, V> Tier<K, V> {
+    /// Inserts a value, returning the key which is no longer needed: the one
+    /// evicted to make room, or the one given if already present.
+    fn insert(&mut self, key: K, value: &V, max_entries: usize) -> Option<K> {
+        self.tick += 1;
+        let entry = (NonNull::from(value), self.tick);
+        if let Some(found) = self.map.get_mut(&key) {
+            *found = entry;
+            return Some(key);
+        }
+        let mut evicted = None;
+        if self.map.len() >= max_entries {
+            // stamps are unique, so this evicts only the least recently used,
+            // and the map is rebuilt to take its key out rather than drop it
+            let oldest = self.map.values().map(|&(_, used)| used).min();
+            let map = mem::replace(&mut self.map, HashMap::with_capacity(max_entries));
+            for (key, found) in map {
+                match Some(found.1) == oldest {
+                    true => evicted = Some(key),
+                    false => drop(self.map.insert(key, found)),
+                }
+            }
+        }
+        self.map.insert(key, entry);
+        evicted
+    }
+}
+
+impl<K, V, S, F> Drop for TieredCache<K, V, S, F> {
+    fn drop(&mut self) {
+        // other threads prune theirs the next time they use any TieredCache,
+        // or else drop them when they exit, and never use them again, as ids
+        // are not reused
+        self.alive.store(false, Ordering::Release);
+        DROPPED.fetch_add(1, Ordering::Release);
+        let tier = TIERS
+            .try_with(|tiers| tiers.borrow_mut().by_id.remove(&self.id))
+            .ok()
+            .flatten();
+        // dropped outside the borrow, in case a key uses a cache
+        drop(tier);
+    }
+}
+
+impl<K, V, S, F> fmt::Debug for TieredCache<K, V, S, F>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TieredCache")
+            .field("l1_entries", &self.l1_entries)
+            .field("l2", &self.l2)
+            .finish_non_exhaustive()
+    }
+}
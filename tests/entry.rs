@@ -0,0 +1,57 @@
+use simple_cache::{Clock, Entry, HashCache, HashCacheConfig};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+#[derive(Clone)]
+struct ManualClock(Arc<Mutex<Instant>>);
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.0.lock().unwrap()
+    }
+}
+
+#[test]
+fn replacing_resets_the_ttl() {
+    let clock = ManualClock(Arc::new(Mutex::new(Instant::now())));
+    let config = HashCacheConfig::builder()
+        .default_ttl(Duration::from_secs(10))
+        .clock(clock.clone())
+        .build();
+    let mut cache: HashCache<u32, u32> = HashCache::with_config(config);
+    cache.insert(1, 1);
+    cache.insert(2, 2);
+    cache.insert(3, 3);
+
+    *clock.0.lock().unwrap() += Duration::from_secs(8);
+    cache.entry(1).and_replace(10);
+    assert_eq!(cache.replace(2, 20), Some(2));
+    match cache.entry(3) {
+        Entry::Occupied(mut entry) => assert_eq!(entry.insert(30), 3),
+        Entry::Vacant(_) => unreachable!(),
+    }
+
+    *clock.0.lock().unwrap() += Duration::from_secs(8);
+    assert_eq!(cache.get(&1), Some(&10));
+    assert_eq!(cache.get(&2), Some(&20));
+    assert_eq!(cache.get(&3), Some(&30));
+}
+
+#[test]
+fn replacing_with_a_heavier_value_makes_room() {
+    let config = HashCacheConfig::builder()
+        .weigher(|_, v: &Vec<u8>| v.len())
+        .max_weight(10)
+        .build();
+    let mut cache: HashCache<u32, Vec<u8>> = HashCache::with_config(config);
+    for k in 0..5 {
+        cache.insert(k, vec![0; 2]);
+    }
+    cache.entry(4).and_replace(vec![1; 6]);
+    assert_eq!(cache.get(&4), Some(&vec![1; 6]));
+    let weight: usize = cache.values().map(Vec::len).sum();
+    assert!(weight <= 10, "{weight}");
+    assert_eq!(cache.values().count(), 3);
+}
@@ -0,0 +1,247 @@
+use simple_cache::{Clock, EvictionPolicy, HashCache, HashCacheConfig};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::BuildHasherDefault,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+#[derive(Clone)]
+struct ManualClock(Arc<Mutex<Instant>>);
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.0.lock().unwrap()
+    }
+}
+
+fn bounded(max_entries: usize, policy: EvictionPolicy) -> HashCache<u32, u32> {
+    let config = HashCacheConfig::builder()
+        .max_entries(max_entries)
+        .eviction_policy(policy)
+        .build();
+    HashCache::with_config(config)
+}
+
+#[test]
+fn lru_evicts_least_recently_used() {
+    let cache = bounded(3, EvictionPolicy::Lru);
+    for k in 0..3 {
+        cache.get_or_insert_with(&k, |&k| k);
+    }
+    cache.get(&0);
+    cache.get_or_insert_with(&3, |&k| k);
+    assert!(cache.get(&1).is_none());
+    cache.get(&2);
+    cache.get_or_insert_with(&4, |&k| k);
+    assert!(cache.get(&0).is_none());
+    for k in [2, 3, 4] {
+        assert_eq!(cache.get(&k), Some(&k));
+    }
+}
+
+#[test]
+fn len_leaves_out_evicted_entries() {
+    let cache = bounded(1, EvictionPolicy::Lru);
+    cache.get_or_insert_with(&1, |&k| k);
+    cache.get_or_insert_with(&2, |&k| k);
+    assert_eq!(cache.len(), 1);
+    assert!(!cache.is_empty());
+}
+
+#[test]
+fn lru_follows_many_uses() {
+    let mut cache = bounded(100, EvictionPolicy::Lru);
+    for k in 0..100 {
+        cache.get_or_insert_with(&k, |&k| k);
+    }
+    // use the even keys, oldest first, so the odd keys are evicted
+    for k in (0..100).step_by(2) {
+        cache.get(&k);
+    }
+    for k in 100..150 {
+        cache.get_or_insert_with(&k, |&k| k);
+    }
+    for k in 0..100 {
+        assert_eq!(cache.get(&k).is_some(), k % 2 == 0, "{k}");
+    }
+    cache.purge();
+    assert_eq!(cache.len(), 100);
+}
+
+#[test]
+fn lru_evicts_expired_first() {
+    let now = Arc::new(Mutex::new(Instant::now()));
+    let config = HashCacheConfig::builder()
+        .max_entries(2)
+        .eviction_policy(EvictionPolicy::Lru)
+        .clock(ManualClock(Arc::clone(&now)))
+        .build();
+    let cache = HashCache::with_config(config);
+    cache.get_or_insert_with(&0, |&k| k);
+    cache.get_or_insert_with_ttl(&1, Duration::from_secs(1), |&k| k);
+    *now.lock().unwrap() += Duration::from_secs(2);
+    cache.get_or_insert_with(&2, |&k| k);
+    assert_eq!(cache.get(&0), Some(&0));
+    assert_eq!(cache.get(&2), Some(&2));
+}
+
+#[test]
+fn clock_gives_referenced_entries_a_second_chance() {
+    let cache = bounded(3, EvictionPolicy::Clock);
+    for k in 0..3 {
+        cache.get_or_insert_with(&k, |&k| k);
+    }
+    cache.get(&0);
+    cache.get(&2);
+    // the sweep passes over 0, and evicts 1
+    cache.get_or_insert_with(&3, |&k| k);
+    assert!(cache.get(&1).is_none());
+    for k in [0, 2, 3] {
+        assert_eq!(cache.get(&k), Some(&k));
+    }
+
+    // an entry referenced before each sweep is never evicted
+    for k in 4..100 {
+        cache.get(&0);
+        cache.get_or_insert_with(&k, |&k| k);
+        assert_eq!(cache.get(&0), Some(&0), "{k}");
+    }
+}
+
+#[test]
+fn clock_evicts_when_everything_is_referenced() {
+    let mut cache = bounded(10, EvictionPolicy::Clock);
+    for k in 0..10 {
+        cache.get_or_insert_with(&k, |&k| k);
+        cache.get(&k);
+    }
+    cache.get_or_insert_with(&10, |&k| k);
+    assert_eq!(cache.values().count(), 10);
+    assert_eq!(cache.get(&10), Some(&10));
+    cache.purge();
+    assert_eq!(cache.len(), 10);
+}
+
+#[test]
+fn sampled_lru_stays_within_bounds() {
+    for samples in [1, 5, 64] {
+        let mut cache = bounded(10, EvictionPolicy::SampledLru { samples });
+        for k in 0..100 {
+            cache.get_or_insert_with(&k, |&k| k);
+            assert_eq!(cache.values().count(), (k as usize + 1).min(10));
+            assert_eq!(cache.get(&k), Some(&k));
+        }
+        cache.purge();
+        assert_eq!(cache.len(), 10);
+    }
+}
+
+#[test]
+fn sampled_lru_keeps_hot_entries() {
+    let cache = bounded(10, EvictionPolicy::SampledLru { samples: 64 });
+    for k in 0..100 {
+        cache.get_or_insert_with(&k, |&k| k);
+        // with this many samples, the hot entry is never the only one seen
+        assert_eq!(cache.get(&0), Some(&0), "{k}");
+    }
+}
+
+#[test]
+fn lfu_keeps_hot_entries() {
+    let mut cache = bounded(4, EvictionPolicy::Lfu);
+    cache.get_or_insert_with(&0, |&k| k);
+    for _ in 0..10 {
+        cache.get(&0);
+    }
+    for k in 1..100 {
+        cache.get_or_insert_with(&k, |&k| k);
+    }
+    assert_eq!(cache.get(&0), Some(&0));
+    // of the cold entries, only the most recent remain
+    assert!((1..97).all(|k| cache.get(&k).is_none()));
+    for k in 97..100 {
+        assert_eq!(cache.get(&k), Some(&k));
+    }
+    cache.purge();
+    assert_eq!(cache.len(), 4);
+}
+
+fn watermarks(high: usize, low: Option<usize>) -> HashCache<u32, u32> {
+    let config = HashCacheConfig::builder().high_watermark(high);
+    let config = match low {
+        Some(low) => config.low_watermark(low),
+        None => config,
+    };
+    HashCache::with_config(config.build())
+}
+
+#[test]
+fn watermarks_trim_on_mut_access() {
+    let mut cache = watermarks(10, Some(5));
+    for k in 0..11 {
+        cache.get_or_insert_with(&k, |&k| k);
+    }
+    for k in 6..11 {
+        cache.get(&k);
+    }
+    // &self methods may grow the cache past the high watermark
+    assert_eq!(cache.len(), 11);
+    cache.shrink_to_fit();
+    assert_eq!(cache.len(), 5);
+    for k in 6..11 {
+        assert_eq!(cache.get(&k), Some(&k));
+    }
+}
+
+#[test]
+fn watermarks_leave_smaller_caches_alone() {
+    let mut cache = watermarks(10, Some(5));
+    for k in 0..10 {
+        cache.get_or_insert_with(&k, |&k| k);
+    }
+    cache.shrink_to_fit();
+    assert_eq!(cache.len(), 10);
+}
+
+#[test]
+fn watermarks_trim_to_high_without_low() {
+    let mut cache = watermarks(10, None);
+    for k in 0..20 {
+        cache.get_or_insert_with(&k, |&k| k);
+    }
+    cache.purge();
+    assert_eq!(cache.len(), 10);
+    assert!((10..20).all(|k| cache.get(&k) == Some(&k)));
+}
+
+/// Looks up 10 hot keys, interleaved with a scan of one-off keys, in a cache
+/// which only has room for the hot keys, and returns how many of them are in
+/// the cache at the end.
+fn hot_keys_after_scan(policy: EvictionPolicy) -> usize {
+    // a fixed hasher, so that collisions in the sketch are the same each run
+    let config = HashCacheConfig::builder()
+        .hasher(BuildHasherDefault::<DefaultHasher>::default())
+        .max_entries(10)
+        .eviction_policy(policy)
+        .build();
+    let cache: HashCache<u32, u32, _> = HashCache::with_config(config);
+    for _ in 0..5 {
+        for k in 0..10 {
+            cache.get_or_insert_with(&k, |&k| k);
+        }
+    }
+    for k in 100..1000 {
+        // a rejected entry is still returned
+        assert_eq!(cache.get_or_insert_with(&k, |&k| k), &k);
+        cache.get_or_insert_with(&(k % 10), |&k| k);
+    }
+    (0..10).filter(|k| cache.get(k).is_some()).count()
+}
+
+#[test]
+fn tinylfu_resists_scans() {
+    assert_eq!(hot_keys_after_scan(EvictionPolicy::TinyLfu), 10);
+    // whereas LRU lets the scan push hot keys out
+    assert!(hot_keys_after_scan(EvictionPolicy::Lru) < 10);
+}
@@ -0,0 +1,144 @@
+use simple_cache::{Clock, HashCache, HashCacheConfig};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+#[derive(Clone)]
+struct ManualClock(Arc<Mutex<Instant>>);
+
+impl ManualClock {
+    fn advance(&self, by: Duration) {
+        *self.0.lock().unwrap() += by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.0.lock().unwrap()
+    }
+}
+
+fn clock() -> ManualClock {
+    ManualClock(Arc::new(Mutex::new(Instant::now())))
+}
+
+const SECOND: Duration = Duration::from_secs(1);
+
+#[test]
+fn mut_access_reclaims_expired_entries() {
+    let ops: [fn(&mut HashCache<u32, u32>); 4] = [
+        |cache| {
+            cache.insert(1000, 0);
+        },
+        |cache| {
+            cache.remove(&1000);
+        },
+        HashCache::shrink_to_fit,
+        |cache| cache.reserve(10),
+    ];
+    for op in ops {
+        let clock = clock();
+        let config = HashCacheConfig::builder()
+            .default_ttl(SECOND)
+            .clock(clock.clone())
+            .build();
+        let mut cache = HashCache::with_config(config);
+        for k in 0..100 {
+            cache.get_or_insert_with(&k, |&k| k);
+        }
+        clock.advance(SECOND / 2);
+        cache.get_or_insert_with_ttl(&100, 10 * SECOND, |&k| k);
+        op(&mut cache);
+        assert!(cache.len() >= 101);
+
+        clock.advance(SECOND);
+        op(&mut cache);
+        assert_eq!(cache.len() - usize::from(cache.contains_key(&1000)), 1);
+        assert_eq!(cache.get(&100), Some(&100));
+        // nothing was left for purge to reclaim
+        assert_eq!(cache.purge(), 0);
+    }
+}
+
+#[test]
+fn mut_access_reclaims_idle_entries() {
+    let clock = clock();
+    let config = HashCacheConfig::builder()
+        .time_to_idle(10 * SECOND)
+        .clock(clock.clone())
+        .build();
+    let mut cache = HashCache::with_config(config);
+    for k in 0..10 {
+        cache.get_or_insert_with(&k, |&k| k);
+    }
+    clock.advance(8 * SECOND);
+    assert_eq!(cache.get(&0), Some(&0));
+    clock.advance(8 * SECOND);
+    cache.shrink_to_fit();
+    assert_eq!(cache.purge(), 0);
+    assert_eq!(cache.len(), 1);
+    assert_eq!(cache.get(&0), Some(&0));
+}
+
+#[test]
+fn stale_entries_are_kept_until_their_window_passes() {
+    let clock = clock();
+    let config = HashCacheConfig::builder()
+        .default_ttl(SECOND)
+        .stale_while_revalidate(5 * SECOND)
+        .on_stale(|_| {})
+        .clock(clock.clone())
+        .build();
+    let mut cache = HashCache::with_config(config);
+    for k in 0..10 {
+        cache.get_or_insert_with(&k, |&k| k);
+    }
+    clock.advance(2 * SECOND);
+    cache.shrink_to_fit();
+    assert_eq!(cache.len(), 10);
+    clock.advance(5 * SECOND);
+    assert_eq!(cache.len(), 0);
+    cache.shrink_to_fit();
+    assert_eq!(cache.purge(), 0);
+}
+
+#[test]
+fn len_leaves_out_expired_entries() {
+    let clock = clock();
+    let config = HashCacheConfig::builder()
+        .time_to_idle(10 * SECOND)
+        .clock(clock.clone())
+        .build();
+    let cache = HashCache::with_config(config);
+    for k in 0..10 {
+        cache.get_or_insert_with(&k, |&k| k);
+    }
+    cache.get_or_insert_with_ttl(&10, 5 * SECOND, |&k| k);
+    assert_eq!(cache.len(), 11);
+    clock.advance(6 * SECOND);
+    assert_eq!(cache.get(&0), Some(&0));
+    assert_eq!(cache.len(), 10);
+    clock.advance(6 * SECOND);
+    assert_eq!(cache.len(), 1);
+    clock.advance(10 * SECOND);
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn bounded_insert_only_counts_live_entries() {
+    let clock = clock();
+    let config = HashCacheConfig::builder().clock(clock.clone()).build();
+    let mut cache = HashCache::with_config(config);
+    cache.get_or_insert_with_ttl(&1, SECOND, |&k| k);
+    cache.get_or_insert_with_ttl(&2, 10 * SECOND, |&k| k);
+    clock.advance(2 * SECOND);
+    assert_eq!(cache.get_or_insert_bounded(&3, 2, |&k| k), (&3, None));
+    assert_eq!(cache.get(&2), Some(&2));
+    assert_eq!(cache.purge(), 0);
+
+    let (v, evicted) = cache.get_or_insert_bounded(&4, 2, |&k| k);
+    assert_eq!(v, &4);
+    assert!(matches!(evicted, Some((2, 2)) | Some((3, 3))));
+    assert_eq!(cache.len(), 2);
+}
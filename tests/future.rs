@@ -0,0 +1,136 @@
+use simple_cache::HashCache;
+use std::{
+    future::{self, Future},
+    pin::{pin, Pin},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll, Wake},
+    thread::{self, Thread},
+    time::Duration,
+};
+
+struct Unpark(Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn block_on<F: Future>(fut: F) -> F::Output {
+    let waker = Arc::new(Unpark(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    let mut fut = pin!(fut);
+    loop {
+        if let Poll::Ready(output) = fut.as_mut().poll(&mut cx) {
+            return output;
+        }
+        thread::park();
+    }
+}
+
+fn poll<F: Future>(fut: Pin<&mut F>) -> Poll<F::Output> {
+    let waker = Arc::new(Unpark(thread::current())).into();
+    fut.poll(&mut Context::from_waker(&waker))
+}
+
+#[test]
+fn insert_async() {
+    let cache: HashCache<u32, u32> = HashCache::new();
+    let v = block_on(cache.get_or_insert_async(&1, |&k| async move { k + 1 }));
+    assert_eq!(*v, 2);
+    let v = block_on(cache.get_or_insert_async(&1, |_| async { unreachable!() }));
+    assert_eq!(*v, 2);
+    assert_eq!(cache.get(&1), Some(&2));
+}
+
+#[test]
+fn insert_async_waits_for_the_leader() {
+    let cache: HashCache<u32, u32> = HashCache::new();
+    let (ready, calls) = (&AtomicBool::new(false), &AtomicUsize::new(0));
+    let load = |&k: &u32| {
+        calls.fetch_add(1, Ordering::SeqCst);
+        future::poll_fn(move |_| match ready.load(Ordering::SeqCst) {
+            true => Poll::Ready(k * 2),
+            false => Poll::Pending,
+        })
+    };
+
+    let mut leader = pin!(cache.get_or_insert_async(&1, load));
+    let mut waiter = pin!(cache.get_or_insert_async(&1, load));
+    assert!(poll(leader.as_mut()).is_pending());
+    assert!(poll(waiter.as_mut()).is_pending());
+    assert_eq!(cache.get(&1), None);
+
+    ready.store(true, Ordering::SeqCst);
+    assert_eq!(poll(waiter.as_mut()), Poll::Pending);
+    assert_eq!(poll(leader.as_mut()), Poll::Ready(&2));
+    assert_eq!(poll(waiter.as_mut()), Poll::Ready(&2));
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn insert_async_takes_over_from_a_dropped_leader() {
+    let cache: HashCache<u32, u32> = HashCache::new();
+    let mut leader = Box::pin(cache.get_or_insert_async(&1, |_| future::pending()));
+    assert!(poll(leader.as_mut()).is_pending());
+
+    let mut waiter = pin!(cache.get_or_insert_async(&1, |&k| async move { k }));
+    assert!(poll(waiter.as_mut()).is_pending());
+    drop(leader);
+    assert_eq!(poll(waiter.as_mut()), Poll::Ready(&1));
+}
+
+#[test]
+fn insert_async_is_send() {
+    fn assert_send<T: Send>(_: T) {}
+    let cache: HashCache<u32, u32> = HashCache::new();
+    assert_send(cache.get_or_insert_async(&1, |_| async { 1 }));
+    assert_send(cache.get_or_try_insert_async(&1, |_| async { Ok::<_, ()>(1) }));
+}
+
+#[test]
+fn try_insert_async_runs_loader_once() {
+    let cache: HashCache<u32, u32> = HashCache::new();
+    let calls = AtomicUsize::new(0);
+    thread::scope(|s| {
+        for _ in 0..8 {
+            s.spawn(|| {
+                let v = block_on(cache.get_or_try_insert_async(&1, |_| {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    async {
+                        thread::sleep(Duration::from_millis(50));
+                        Ok::<_, ()>(7)
+                    }
+                }));
+                assert_eq!(v, Ok(&7));
+            });
+        }
+    });
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn try_insert_async_retries_after_errors() {
+    let cache: HashCache<u32, u32> = HashCache::new();
+    let calls = AtomicUsize::new(0);
+    thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|| {
+                let v = block_on(cache.get_or_try_insert_async(&1, |_| {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    async {
+                        thread::sleep(Duration::from_millis(10));
+                        Err::<u32, _>("nope")
+                    }
+                }));
+                assert_eq!(v, Err("nope"));
+            });
+        }
+    });
+    // every caller led in turn, as nobody installed a value
+    assert_eq!(calls.load(Ordering::SeqCst), 4);
+    assert!(!cache.contains_key(&1));
+}
@@ -0,0 +1,54 @@
+use simple_cache::GuardedCache;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+struct Counted(Arc<AtomicUsize>);
+
+impl Drop for Counted {
+    fn drop(&mut self) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn evicts_least_recently_used() {
+    let cache = GuardedCache::with_max_entries(3);
+    for k in 0..3 {
+        cache.get_or_insert_with(&k, |&k| k);
+    }
+    cache.get(&0);
+    cache.get_or_insert_with(&3, |&k| k);
+    assert!(!cache.contains_key(&1));
+    cache.get(&2);
+    cache.get_or_insert_with(&4, |&k| k);
+    assert!(!cache.contains_key(&0));
+    assert!([2, 3, 4].iter().all(|k| cache.contains_key(k)));
+
+    cache.remove(&3);
+    for k in 5..1000 {
+        assert_eq!(*cache.get_or_insert_with(&k, |&k| k), k);
+        assert!(cache.len() <= 3);
+    }
+    assert!([997, 998, 999].iter().all(|k| cache.contains_key(k)));
+}
+
+#[test]
+fn frees_evicted_entries_once_unguarded() {
+    let drops = Arc::new(AtomicUsize::new(0));
+    let cache = GuardedCache::with_max_entries(1);
+    let counted = |_: &u32| Counted(Arc::clone(&drops));
+    let guard = cache.get_or_insert_with(&1, counted);
+    cache.get_or_insert_with(&2, counted);
+    let removed = cache.get_or_insert_with(&2, counted);
+    assert!(cache.remove(&2));
+    assert_eq!(drops.load(Ordering::SeqCst), 0);
+
+    drop(guard);
+    assert!(cache.get(&1).is_none());
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+    drop(removed);
+    assert!(!cache.remove(&2));
+    assert_eq!(drops.load(Ordering::SeqCst), 2);
+}
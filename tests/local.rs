@@ -0,0 +1,37 @@
+use simple_cache::ThreadLocalCache;
+use std::{sync::mpsc, sync::Arc, thread};
+
+#[test]
+fn dropped_caches_leave_no_thread_cache_behind() {
+    type Cache = ThreadLocalCache<u32, Arc<u32>>;
+    let (send, recv) = mpsc::channel::<Option<(Arc<Cache>, Arc<u32>)>>();
+    let (done, wait) = mpsc::channel();
+    // a pooled thread, which outlives the cache
+    let worker = thread::spawn(move || {
+        let other = Cache::new();
+        for message in recv {
+            match message {
+                Some((cache, value)) => {
+                    let found = cache.get_or_insert_with(&1, |_| value);
+                    assert_eq!(*found, 1);
+                }
+                None => assert!(other.with(|other| other.is_empty())),
+            }
+            done.send(()).unwrap();
+        }
+    });
+
+    let value = Arc::new(1);
+    let cache = Arc::new(Cache::new());
+    send.send(Some((Arc::clone(&cache), Arc::clone(&value))))
+        .unwrap();
+    wait.recv().unwrap();
+    // in the worker's cache
+    assert_eq!(Arc::strong_count(&value), 2);
+    drop(cache);
+    send.send(None).unwrap();
+    wait.recv().unwrap();
+    assert_eq!(Arc::strong_count(&value), 1);
+    drop(send);
+    worker.join().unwrap();
+}
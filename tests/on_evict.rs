@@ -0,0 +1,71 @@
+use simple_cache::{EvictionPolicy, HashCache, HashCacheConfig};
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Mutex},
+};
+
+type Evicted = Arc<Mutex<Vec<(u32, u32)>>>;
+
+fn observed(max_entries: usize, policy: EvictionPolicy) -> (HashCache<u32, u32>, Evicted) {
+    let evicted = Evicted::default();
+    let log = Arc::clone(&evicted);
+    let config = HashCacheConfig::builder()
+        .max_entries(max_entries)
+        .eviction_policy(policy)
+        .on_evict(move |k, v| log.lock().unwrap().push((k, *v)))
+        .build();
+    (HashCache::with_config(config), evicted)
+}
+
+#[test]
+fn retain_survives_a_panicking_predicate() {
+    for policy in [EvictionPolicy::Lru, EvictionPolicy::Clock] {
+        let (mut cache, _) = observed(4, policy);
+        let fill = |cache: &HashCache<u32, u32>| {
+            for k in 0..8 {
+                cache.get_or_insert_with(&k, |&k| k);
+            }
+        };
+        // panics partway through, having kept one entry and dropped another
+        let mut seen = 0;
+        let mut third = |&k: &u32| {
+            seen += 1;
+            match seen % 3 {
+                0 => panic!("matched {k}"),
+                n => n == 1,
+            }
+        };
+        fill(&cache);
+        let panicked = panic::catch_unwind(AssertUnwindSafe(|| cache.retain(|k, _| third(k))));
+        assert!(panicked.is_err());
+        fill(&cache);
+        let panicked = panic::catch_unwind(AssertUnwindSafe(|| {
+            cache.invalidate_matching(|k, _| third(k))
+        }));
+        assert!(panicked.is_err());
+
+        for k in 10..50 {
+            assert_eq!(cache.get_or_insert_with(&k, |&k| k), &k);
+            assert!(cache.values().count() <= 4);
+        }
+        cache.retain(|&k, _| k != 49);
+        assert_eq!(cache.len(), cache.values().count());
+        assert!(cache.len() <= 3);
+        assert!(cache.values().all(|&v| (10..49).contains(&v)));
+    }
+}
+
+#[test]
+fn replaced_values_are_reclaimed() {
+    let (mut cache, evicted) = observed(10, EvictionPolicy::Lru);
+    for k in 0..4 {
+        cache.insert(k, k);
+    }
+    cache.insert(0, 10);
+    cache.entry(1).and_replace(11).and_replace(21);
+    // handed back to the caller instead
+    assert_eq!(cache.replace(2, 12), Some(2));
+    assert_eq!(cache.remove(&3), Some(3));
+    assert_eq!(*evicted.lock().unwrap(), [(0, 0), (1, 1), (1, 11)]);
+    assert_eq!(cache.get(&1), Some(&21));
+}
@@ -0,0 +1,59 @@
+use simple_cache::{HashCache, HashCacheConfig, PoisonPolicy};
+use std::panic::{self, AssertUnwindSafe};
+
+/// A cache whose weigher panics on key 1, with the shard's lock held.
+fn cache(policy: PoisonPolicy) -> HashCache<u32, u32> {
+    let config = HashCacheConfig::builder()
+        .weigher(|&k: &u32, _: &u32| if k == 1 { panic!("weighed 1") } else { 1 })
+        .on_poison(policy)
+        .build();
+    HashCache::with_config(config)
+}
+
+fn panic_message<R>(f: impl FnOnce() -> R) -> Option<String> {
+    let payload = panic::catch_unwind(AssertUnwindSafe(f)).err()?;
+    let message = match payload.downcast::<&str>() {
+        Ok(message) => message.to_string(),
+        Err(payload) => *payload.downcast::<String>().unwrap(),
+    };
+    Some(message)
+}
+
+#[test]
+fn ignore_recovers_poisoned_locks() {
+    let mut cache = cache(PoisonPolicy::Ignore);
+    cache.get_or_insert_with(&0, |&k| k);
+    let message = panic_message(|| *cache.get_or_insert_with(&1, |&k| k));
+    assert_eq!(message.as_deref(), Some("weighed 1"));
+
+    assert_eq!(cache.get(&0), Some(&0));
+    assert_eq!(cache.get(&1), None);
+    assert_eq!(cache.get_or_insert_with(&2, |&k| k), &2);
+    cache.clear();
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn panic_fails_fast_on_poisoned_locks() {
+    let mut cache = cache(PoisonPolicy::Panic);
+    cache.get_or_insert_with(&0, |&k| k);
+    let message = panic_message(|| *cache.get_or_insert_with(&1, |&k| k));
+    assert_eq!(message.as_deref(), Some("weighed 1"));
+
+    let poisoned = Some("cache lock poisoned by a panic");
+    let message = panic_message(|| cache.get(&0).copied());
+    assert_eq!(message.as_deref(), poisoned);
+    let message = panic_message(|| *cache.get_or_insert_with(&2, |&k| k));
+    assert_eq!(message.as_deref(), poisoned);
+    let message = panic_message(|| cache.clear());
+    assert_eq!(message.as_deref(), poisoned);
+}
+
+#[test]
+fn panics_outside_locks_do_not_poison() {
+    let cache = cache(PoisonPolicy::Panic);
+    let message = panic_message(|| *cache.get_or_insert_with(&0, |_| panic!("computed 0")));
+    assert_eq!(message.as_deref(), Some("computed 0"));
+    assert_eq!(cache.get(&0), None);
+    assert_eq!(cache.get_or_insert_with(&0, |&k| k), &0);
+}
@@ -0,0 +1,105 @@
+use simple_cache::{HashCache, HashCacheConfig};
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+fn reusing<V>(max_entries: Option<usize>) -> HashCache<u32, V> {
+    let mut builder = HashCacheConfig::builder().reuse_allocations(true);
+    if let Some(max_entries) = max_entries {
+        builder = builder.max_entries(max_entries);
+    }
+    HashCache::with_config(builder.build())
+}
+
+struct Counted(u32, Arc<AtomicUsize>);
+
+impl Drop for Counted {
+    fn drop(&mut self) {
+        self.1.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn refills_reuse_cleared_allocations() {
+    let mut cache = reusing::<[u64; 4]>(None);
+    for k in 0..100 {
+        cache.get_or_insert_with(&k, |&k| [k.into(); 4]);
+    }
+    let addresses: HashSet<_> = (0..100)
+        .map(|k| cache.get(&k).unwrap() as *const _)
+        .collect();
+    cache.clear();
+
+    for k in 100..200 {
+        let v = cache.get_or_insert_with(&k, |&k| [k.into(); 4]);
+        assert!(addresses.contains(&(v as *const _)));
+    }
+    assert!((100..200).all(|k| cache.get(&k) == Some(&[k.into(); 4])));
+}
+
+#[test]
+fn refills_keep_values_intact() {
+    let mut cache = reusing::<Vec<u32>>(None);
+    for round in 0..4 {
+        for k in 0..50 {
+            cache.get_or_insert_with(&k, |&k| vec![k + round; k as usize]);
+        }
+        for k in 0..50 {
+            assert_eq!(cache.get(&k), Some(&vec![k + round; k as usize]));
+        }
+        cache.clear();
+    }
+}
+
+#[test]
+fn values_are_dropped_once() {
+    let drops = Arc::new(AtomicUsize::new(0));
+    let mut cache = reusing::<Counted>(Some(20));
+    let counted = |&k: &u32| Counted(k, Arc::clone(&drops));
+    for k in 0..40 {
+        cache.get_or_insert_with(&k, counted);
+    }
+    cache.purge();
+    assert_eq!(drops.load(Ordering::SeqCst), 20);
+
+    cache.retain(|&k, _| k % 2 == 0);
+    assert_eq!(drops.load(Ordering::SeqCst), 30);
+    let removed = cache.values().next().unwrap().0;
+    assert_eq!(cache.remove(&removed).map(|v| v.0), Some(removed));
+    assert_eq!(drops.load(Ordering::SeqCst), 31);
+    cache.clear();
+    assert_eq!(drops.load(Ordering::SeqCst), 40);
+
+    for k in 0..10 {
+        cache.get_or_insert_with(&k, counted);
+    }
+    drop(cache);
+    assert_eq!(drops.load(Ordering::SeqCst), 50);
+}
+
+#[test]
+fn zero_sized_values() {
+    let mut cache = reusing::<()>(None);
+    for _ in 0..2 {
+        for k in 0..10 {
+            cache.get_or_insert_with(&k, |_| ());
+        }
+        assert_eq!(cache.len(), 10);
+        cache.clear();
+    }
+}
+
+#[test]
+fn extend_drops_overwritten_values() {
+    let drops = Arc::new(AtomicUsize::new(0));
+    let mut cache = reusing::<Counted>(None);
+    let counted = |k: u32| (k, Counted(k, Arc::clone(&drops)));
+    cache.extend((0..10).map(counted));
+    cache.extend((5..15).map(counted));
+    assert_eq!(drops.load(Ordering::SeqCst), 5);
+    assert_eq!(cache.len(), 15);
+}
@@ -0,0 +1,46 @@
+use simple_cache::{EvictionPolicy, HashCache, HashCacheConfig};
+use std::{cell::Cell, collections::hash_map::RandomState};
+
+type Cache = HashCache<u32, u32, RandomState, fn(&u32) -> u32>;
+
+thread_local! {
+    static CACHE: Cell<Option<&'static Cache>> = Cell::new(None);
+}
+
+/// Provides each key offset from the value of key 0, looked up in the cache
+/// being extended.
+fn offset(&n: &u32) -> u32 {
+    let cache = CACHE.with(Cell::get).unwrap();
+    cache.get(&0).map_or(n, |base| base + n)
+}
+
+#[test]
+fn extend_with_provider_can_use_the_cache() {
+    let cache: &'static Cache = Box::leak(Box::new(HashCache::with_provider(offset as _)));
+    CACHE.with(|c| c.set(Some(cache)));
+    cache.get_or_insert_with(&0, |_| 100);
+    cache.get_or_insert_with(&1, |_| 1);
+    cache.extend_with_provider([0, 1, 2, 3]);
+    assert_eq!(cache.get(&0), Some(&100));
+    assert_eq!(cache.get(&1), Some(&1));
+    assert_eq!(cache.get(&2), Some(&102));
+    assert_eq!(cache.get(&3), Some(&103));
+}
+
+#[test]
+fn warming_does_not_use_present_entries() {
+    let config = HashCacheConfig::builder()
+        .max_entries(2)
+        .eviction_policy(EvictionPolicy::Lru)
+        .provider((|&n| n) as fn(&u32) -> u32)
+        .build();
+    let cache: Cache = HashCache::with_config(config);
+    assert_eq!(cache.get_or_insert_optional(&0, |_| None), None);
+    cache.get_or_insert_with(&1, |&k| k);
+    cache.warm([0, 1]);
+    assert_eq!(cache.access_count(&1), Some(0));
+    // 0 is still the least recently used
+    cache.get_or_insert_with(&2, |&k| k);
+    assert_eq!(cache.get(&1), Some(&1));
+    assert_eq!(cache.get(&2), Some(&2));
+}
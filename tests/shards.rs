@@ -0,0 +1,80 @@
+use simple_cache::{HashCache, HashCacheConfig};
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+fn sharded(shards: usize) -> HashCache<u32, u32> {
+    HashCache::with_config(HashCacheConfig::builder().shards(shards).build())
+}
+
+#[test]
+fn concurrent_inserts_across_shards() {
+    let mut cache = sharded(4);
+    let calls: Vec<AtomicUsize> = (0..1000).map(|_| AtomicUsize::new(0)).collect();
+    thread::scope(|s| {
+        for _ in 0..8 {
+            s.spawn(|| {
+                for k in 0..1000 {
+                    let v = cache.get_or_insert_with(&k, |&k| {
+                        calls[k as usize].fetch_add(1, Ordering::SeqCst);
+                        k * 2
+                    });
+                    assert_eq!(*v, k * 2);
+                }
+            });
+        }
+    });
+    assert!(calls.iter().all(|calls| calls.load(Ordering::SeqCst) == 1));
+    assert_eq!(cache.len(), 1000);
+    assert_eq!(cache.values().count(), 1000);
+    assert_eq!((&cache.iter()).into_iter().count(), 1000);
+
+    assert_eq!(cache.remove(&5), Some(10));
+    cache.extend((0..10).map(|k| (k, k)));
+    assert_eq!(cache.get(&5), Some(&5));
+    assert_eq!(cache.get(&10), Some(&20));
+    let mut all: Vec<_> = cache.clone().into_iter().collect();
+    all.sort_unstable();
+    assert_eq!(all.len(), 1000);
+    assert_eq!(all[5], (5, 5));
+    assert_eq!(all[500], (500, 1000));
+}
+
+#[test]
+fn readers_and_writers_across_shards() {
+    let cache = sharded(8);
+    thread::scope(|s| {
+        for t in 0..4 {
+            let cache = &cache;
+            s.spawn(move || {
+                for k in (t..2000).step_by(4) {
+                    cache.get_or_insert_with(&k, |&k| k + 1);
+                }
+            });
+            s.spawn(move || {
+                for k in 0..2000 {
+                    if let Some(&v) = cache.get(&k) {
+                        assert_eq!(v, k + 1);
+                    }
+                }
+            });
+        }
+    });
+    assert!((0..2000).all(|k| cache.get(&k) == Some(&(k + 1))));
+}
+
+#[test]
+fn one_shard_behaves_the_same() {
+    for shards in [0, 1, 3] {
+        let mut cache = sharded(shards);
+        for k in 0..100 {
+            cache.get_or_insert_with(&k, |&k| k);
+        }
+        cache.retain(|&k, _| k % 2 == 0);
+        assert_eq!(cache.len(), 50);
+        assert!((0..100).all(|k| cache.get(&k).is_some() == (k % 2 == 0)));
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,69 @@
+use simple_cache::TieredCache;
+use std::{sync::mpsc, sync::Arc, thread};
+
+thread_local! {
+    static OTHER: TieredCache<u32, u32> = TieredCache::new(4);
+}
+
+/// A key which uses another cache whenever it is cloned or dropped.
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct Reentrant(u32);
+
+fn use_other() {
+    OTHER.with(|other| assert_eq!(other.get_or_insert_with(&0, |_| 0), &0));
+}
+
+impl Clone for Reentrant {
+    fn clone(&self) -> Self {
+        use_other();
+        Reentrant(self.0)
+    }
+}
+
+impl Drop for Reentrant {
+    fn drop(&mut self) {
+        use_other();
+    }
+}
+
+#[test]
+fn keys_may_use_a_cache() {
+    let cache = TieredCache::new(1);
+    for k in [1, 2, 2, 1, 3] {
+        assert_eq!(cache.get_or_insert_with(&Reentrant(k), |k| k.0), &k);
+        assert_eq!(cache.get(&Reentrant(k)), Some(&k));
+    }
+}
+
+#[test]
+fn dropped_caches_leave_no_l1_behind() {
+    type Cache = TieredCache<Arc<u32>, u32>;
+    let (send, recv) = mpsc::channel::<Option<(Arc<Cache>, Arc<u32>)>>();
+    let (done, wait) = mpsc::channel();
+    // a pooled thread, which outlives the cache
+    let worker = thread::spawn(move || {
+        let other = Cache::new(4);
+        for message in recv {
+            match message {
+                Some((cache, key)) => assert_eq!(cache.get_or_insert_with(&key, |_| 1), &1),
+                None => assert_eq!(other.get(&Arc::new(0)), None),
+            }
+            done.send(()).unwrap();
+        }
+    });
+
+    let key = Arc::new(1);
+    let cache = Arc::new(Cache::new(4));
+    send.send(Some((Arc::clone(&cache), Arc::clone(&key))))
+        .unwrap();
+    wait.recv().unwrap();
+    // in L2, and in the worker's L1
+    assert_eq!(Arc::strong_count(&key), 3);
+    drop(cache);
+    assert_eq!(Arc::strong_count(&key), 2);
+    send.send(None).unwrap();
+    wait.recv().unwrap();
+    assert_eq!(Arc::strong_count(&key), 1);
+    drop(send);
+    worker.join().unwrap();
+}
@@ -0,0 +1,81 @@
+use simple_cache::{HashCache, HashCacheConfig};
+
+fn bytes(max_weight: usize) -> HashCache<u32, Vec<u8>> {
+    let config = HashCacheConfig::builder()
+        .weigher(|_, v: &Vec<u8>| v.len())
+        .max_weight(max_weight)
+        .build();
+    HashCache::with_config(config)
+}
+
+#[test]
+fn evicts_by_byte_length() {
+    let mut cache = bytes(10);
+    cache.get_or_insert_with(&1, |_| vec![0; 4]);
+    cache.get_or_insert_with(&2, |_| vec![0; 4]);
+    cache.get_or_insert_with(&3, |_| vec![0; 4]);
+    assert!(cache.get(&1).is_none());
+    assert!(cache.get(&2).is_some());
+    assert!(cache.get(&3).is_some());
+
+    // an entry heavier than the bound evicts everything else
+    cache.get_or_insert_with(&4, |_| vec![0; 20]);
+    assert!(cache.get(&2).is_none());
+    assert!(cache.get(&3).is_none());
+    assert!(cache.get(&4).is_some());
+
+    // removing it gives its weight back
+    cache.remove(&4);
+    cache.get_or_insert_with(&5, |_| vec![0; 6]);
+    cache.get_or_insert_with(&6, |_| vec![0; 4]);
+    assert!(cache.get(&5).is_some());
+    assert!(cache.get(&6).is_some());
+}
+
+#[test]
+fn retain_gives_weight_back() {
+    let mut cache = bytes(10);
+    cache.get_or_insert_with(&1, |_| vec![0; 5]);
+    cache.get_or_insert_with(&2, |_| vec![0; 5]);
+    cache.retain(|&k, _| k != 1);
+    cache.get_or_insert_with(&3, |_| vec![0; 5]);
+    assert!(cache.get(&2).is_some());
+    assert!(cache.get(&3).is_some());
+}
+
+#[test]
+fn clone_keeps_weight() {
+    let cache = bytes(10);
+    cache.get_or_insert_with(&1, |_| vec![0; 4]);
+    cache.get_or_insert_with(&2, |_| vec![0; 4]);
+    cache.get_or_insert_with(&3, |_| vec![0; 4]);
+
+    let mut clone = cache.clone();
+    assert_eq!(clone.remove(&2), Some(vec![0; 4]));
+    assert_eq!(clone.remove(&1), None);
+    clone.get_or_insert_with(&4, |_| vec![0; 6]);
+    assert!(clone.get(&3).is_some());
+    assert!(clone.get(&4).is_some());
+    clone.get_or_insert_with(&5, |_| vec![0; 1]);
+    assert!(clone.get(&3).is_none());
+
+    // the original is unaffected
+    assert!(cache.get(&2).is_some());
+}
+
+#[test]
+fn clone_of_bounded_cache() {
+    let config = HashCacheConfig::builder().max_entries(2).build();
+    let cache: HashCache<u32, u32> = HashCache::with_config(config);
+    cache.get_or_insert_with(&1, |&k| k);
+    cache.get_or_insert_with(&2, |&k| k);
+    cache.get_or_insert_with(&3, |&k| k);
+
+    let mut clone = cache.clone();
+    clone.remove(&3);
+    clone.get_or_insert_with(&4, |&k| k);
+    clone.get_or_insert_with(&5, |&k| k);
+    assert_eq!(clone.values().count(), 2);
+    clone.purge();
+    assert_eq!(clone.len(), 2);
+}